@@ -0,0 +1,108 @@
+//! `--adb`, which reads an Android device's /proc over adb into a snapshot
+//! that the report is then built from, like one opened with `--from`. Nothing
+//! needs to be installed on the device; its shell and toybox do the reading.
+
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::Path;
+use std::process::Command;
+
+use crate::capture::Snapshot;
+use crate::procfs::SNAPSHOT_TIMESTAMP_FILE;
+
+/// Starts every record in the stream the device writes. Its file contents may
+/// contain anything, but command lines are the only binary ones, and for them
+/// to contain this, an empty argument would need to be followed by this one.
+const MARKER: &[u8] = b"\0\0memory-adb-record ";
+
+/// Writes the files the report reads as records: "file PATH" followed by the
+/// contents, "failed PATH" if they could not be read after all, and "link PATH"
+/// followed by the target. The device model stands in for the hostname, which
+/// is "localhost" on all of them.
+const SCRIPT: &str = "\
+    r() { printf '\\0\\0memory-adb-record %s %s\\n' \"$1\" \"$2\"; }; \
+    r file timestamp; date +%s; \
+    r file proc/sys/kernel/hostname; getprop ro.product.model 2>/dev/null || cat /proc/sys/kernel/hostname; \
+    for f in meminfo swaps stat pressure/memory; do r file proc/$f; cat /proc/$f 2>/dev/null || r failed proc/$f; done; \
+    cd /proc; \
+    for p in [0-9]*; do \
+        for f in status stat cmdline comm cgroup smaps_rollup oom_score oom_score_adj; do \
+            r file proc/$p/$f; cat $p/$f 2>/dev/null || r failed proc/$p/$f; \
+        done; \
+        r link proc/$p/exe; readlink $p/exe 2>/dev/null; \
+    done; true";
+
+/// Splits the device's output into (kind, path, contents) records
+fn records(data: &[u8]) -> Vec<(&str, &str, &[u8])> {
+    let mut records = vec![];
+    let mut rest = data;
+    while let Some(start) = rest.windows(MARKER.len()).position(|w| w == MARKER) {
+        rest = &rest[start + MARKER.len()..];
+        let end = rest.windows(MARKER.len()).position(|w| w == MARKER).unwrap_or(rest.len());
+        let (record, next) = rest.split_at(end);
+        rest = next;
+        let (header, contents) = match record.iter().position(|&b| b == b'\n') {
+            Some(i) => (&record[..i], &record[i + 1..]),
+            None => (record, &record[record.len()..]),
+        };
+        let Some((kind, path)) = std::str::from_utf8(header).ok().and_then(|h| h.split_once(' ')) else {
+            continue;
+        };
+        records.push((kind, path, contents));
+    }
+    records
+}
+
+/// Recreates the files the device wrote in root; only the last record of a
+/// path counts, so that "failed" removes a file again
+fn write_records(root: &Path, data: &[u8]) {
+    let mut files = HashMap::new();
+    for (kind, path, contents) in records(data) {
+        // Paths come from the device; keep them below root
+        if path.split('/').any(|c| c.is_empty() || c == "." || c == "..") {
+            continue;
+        }
+        files.insert(path, (kind, contents));
+    }
+    for (path, (kind, contents)) in files {
+        let target = root.join(path);
+        if let Some(parent) = target.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        match kind {
+            "file" => {
+                let _ = fs::write(target, contents);
+            }
+            "link" => {
+                let link = String::from_utf8_lossy(contents);
+                let link = link.trim_end_matches('\n');
+                if !link.is_empty() {
+                    let _ = symlink(link, target);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Reads the device with the given serial, or the only one connected, into a
+/// temporary snapshot
+pub fn pull(serial: Option<&str>) -> Result<Snapshot, String> {
+    let mut command = Command::new("adb");
+    if let Some(serial) = serial {
+        command.args(["-s", serial]);
+    }
+    // Unlike shell, exec-out passes binary output through unchanged
+    let output = command.args(["exec-out", SCRIPT]).output().map_err(|e| format!("failed to run adb: {e}"))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(stderr.lines().last().unwrap_or("adb failed").to_string());
+    }
+    let snapshot = Snapshot::temporary().map_err(|e| format!("failed to create a temporary directory: {e}"))?;
+    write_records(&snapshot.root, &output.stdout);
+    if !snapshot.root.join("proc/meminfo").exists() || !snapshot.root.join(SNAPSHOT_TIMESTAMP_FILE).exists() {
+        return Err("the device did not return /proc/meminfo".to_string());
+    }
+    Ok(snapshot)
+}
@@ -0,0 +1,85 @@
+//! The `blame` subcommand: samples twice, some time apart, and attributes the
+//! growth in between to groups. This is the diff workflow of `compare`,
+//! without saving and passing around report files.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use crate::model::Report;
+use crate::output::age;
+use crate::sys;
+
+/// How one group changed between the two samples
+#[derive(Default)]
+struct Change {
+    before_kb: u64,
+    after_kb: u64,
+    started: usize,
+    exited: usize,
+}
+
+impl Change {
+    fn growth_kb(&self) -> i64 {
+        self.after_kb as i64 - self.before_kb as i64
+    }
+}
+
+fn changes(before: &Report, after: &Report) -> Vec<(String, Change)> {
+    let processes = |report: &Report| -> HashSet<(u32, String)> { report.samples().map(|p| (p.pid, p.name.clone())).collect() };
+    let (before_processes, after_processes) = (processes(before), processes(after));
+    let mut changes: HashMap<String, Change> = HashMap::new();
+    for group in &before.groups {
+        let change = changes.entry(group.name.clone()).or_default();
+        change.before_kb = group.rss_kb;
+        change.exited = group.processes.iter().filter(|p| !after_processes.contains(&(p.pid, p.name.clone()))).count();
+    }
+    for group in &after.groups {
+        let change = changes.entry(group.name.clone()).or_default();
+        change.after_kb = group.rss_kb;
+        change.started = group.processes.iter().filter(|p| !before_processes.contains(&(p.pid, p.name.clone()))).count();
+    }
+    let mut changes: Vec<(String, Change)> = changes.into_iter().collect();
+    changes.sort_by(|(a_name, a), (b_name, b)| b.growth_kb().cmp(&a.growth_kb()).then_with(|| a_name.cmp(b_name)));
+    changes
+}
+
+/// Samples now and again after duration, or when interrupted with Ctrl-C,
+/// and prints the groups that grew the most first
+pub fn blame(out: &mut dyn Write, mut build: impl FnMut() -> Report, duration: Option<Duration>, limit: usize) -> io::Result<()> {
+    let before = build();
+    let start = Instant::now();
+    sys::catch_interrupt();
+    match duration {
+        Some(d) => eprintln!("Sampling again in {}, or press Ctrl-C to do so earlier", age(d.as_secs().max(1))),
+        None => eprintln!("Press Ctrl-C to sample again"),
+    }
+    while !sys::interrupted() && duration.is_none_or(|d| start.elapsed() < d) {
+        let left = duration.map_or(Duration::MAX, |d| d.saturating_sub(start.elapsed()));
+        std::thread::sleep(left.min(Duration::from_millis(100)));
+    }
+    let after = build();
+
+    writeln!(out, "Changes over {}", age(start.elapsed().as_secs()))?;
+    writeln!(out)?;
+    writeln!(out, "{:<35} {:>12} {:>12} {:>12} {:>8} {:>8}", "Application", "Before(MB)", "After(MB)", "Change(MB)", "Started", "Exited")?;
+    let changes = changes(&before, &after);
+    let mb = |kb: u64| kb as f64 / 1024.0;
+    for (name, c) in changes.iter().take(limit) {
+        let growth = c.growth_kb() as f64 / 1024.0;
+        writeln!(out, "{:<35} {:>12.2} {:>12.2} {:>+12.2} {:>8} {:>8}", name, mb(c.before_kb), mb(c.after_kb), growth, c.started, c.exited)?;
+    }
+    let (before_kb, after_kb) = (before.groups.iter().map(|g| g.rss_kb).sum(), after.groups.iter().map(|g| g.rss_kb).sum());
+    let (started, exited) = (changes.iter().map(|(_, c)| c.started).sum::<usize>(), changes.iter().map(|(_, c)| c.exited).sum::<usize>());
+    writeln!(out)?;
+    writeln!(
+        out,
+        "{:<35} {:>12.2} {:>12.2} {:>+12.2} {:>8} {:>8}",
+        "Total",
+        mb(before_kb),
+        mb(after_kb),
+        (after_kb as i64 - before_kb as i64) as f64 / 1024.0,
+        started,
+        exited
+    )
+}
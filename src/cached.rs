@@ -0,0 +1,96 @@
+//! The `cached` subcommand: how much of some files is held in the page cache.
+//!
+//! The page cache makes up a large part of "used" memory on many machines.
+//! This checks page by page (using mincore) which parts of the given files or
+//! directories are resident.
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::sys::{page_size, resident_pages};
+
+#[derive(Default)]
+struct Usage {
+    files: u64,
+    size: u64,
+    cached: u64,
+    /// Files and directories below the given paths that couldn't be read
+    skipped: u64,
+}
+
+/// Adds up the files at path. Symbolic links are only followed for the paths
+/// given on the command line, so that no file is counted twice.
+fn scan(path: &Path, usage: &mut Usage, top_level: bool) {
+    let meta = if top_level { fs::metadata(path) } else { fs::symlink_metadata(path) };
+    let meta = match meta {
+        Ok(meta) => meta,
+        Err(e) => {
+            if top_level {
+                eprintln!("Skipping {}: {e}", path.display());
+            } else {
+                usage.skipped += 1;
+            }
+            return;
+        }
+    };
+    if meta.is_dir() {
+        let Ok(entries) = fs::read_dir(path) else {
+            usage.skipped += 1;
+            return;
+        };
+        for entry in entries.flatten() {
+            scan(&entry.path(), usage, false);
+        }
+    } else if meta.is_file() {
+        let Ok((_, resident)) = File::open(path).and_then(|file| resident_pages(&file, meta.len() as usize)) else {
+            usage.skipped += 1;
+            return;
+        };
+        usage.files += 1;
+        // The last page is only partially part of the file
+        usage.size += meta.len();
+        usage.cached += ((resident * page_size()) as u64).min(meta.len());
+    }
+}
+
+fn mb(bytes: u64) -> f64 {
+    bytes as f64 / 1024.0 / 1024.0
+}
+
+pub fn print_cached(out: &mut dyn Write, paths: &[String]) -> io::Result<()> {
+    writeln!(out, "{:<50} {:>7} {:>12} {:>12} {:>7}", "Path", "Files", "Size(MB)", "Cached(MB)", "%")?;
+    let mut total = Usage::default();
+    for path in paths {
+        let mut usage = Usage::default();
+        scan(Path::new(path), &mut usage, true);
+        let pct = if usage.size > 0 { usage.cached as f64 * 100.0 / usage.size as f64 } else { 0.0 };
+        writeln!(
+            out,
+            "{:<50} {:>7} {:>12.2} {:>12.2} {:>6.1}%",
+            path,
+            usage.files,
+            mb(usage.size),
+            mb(usage.cached),
+            pct
+        )?;
+        total.files += usage.files;
+        total.size += usage.size;
+        total.cached += usage.cached;
+        total.skipped += usage.skipped;
+    }
+    if paths.len() > 1 {
+        writeln!(
+            out,
+            "{:<50} {:>7} {:>12.2} {:>12.2}",
+            "Total",
+            total.files,
+            mb(total.size),
+            mb(total.cached)
+        )?;
+    }
+    if total.skipped > 0 {
+        writeln!(out, "\nSkipped files and directories that could not be read: {}", total.skipped)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,179 @@
+//! The `capture` subcommand, which archives the system files memory reads, and
+//! `--from`, which reads them from such an archive instead. This allows
+//! analyzing a machine's memory after the fact, elsewhere.
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, symlink};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::cgroup;
+use crate::procfs::{SNAPSHOT_TIMESTAMP_FILE, is_numeric_dir};
+use crate::session::SESSIONS_DIR;
+
+/// Files read for the whole system
+const SYSTEM_FILES: [&str; 10] = [
+    "/proc/meminfo",
+    "/proc/swaps",
+    "/proc/mounts",
+    "/proc/self/mounts",
+    "/proc/sysvipc/shm",
+    "/proc/pressure/memory",
+    "/proc/stat",
+    "/proc/sys/kernel/hostname",
+    "/proc/self/smaps",
+    "/etc/passwd",
+];
+/// Files read for every process in /proc/[pid]
+const PROCESS_FILES: [&str; 13] = [
+    "status",
+    "stat",
+    "cmdline",
+    "comm",
+    "cgroup",
+    "smaps",
+    "smaps_rollup",
+    "numa_maps",
+    "maps",
+    "oom_score",
+    "oom_score_adj",
+    "ksm_stat",
+    "ksm_merging_pages",
+];
+
+/// Copies a file into the snapshot directory, if it can be read
+fn copy(dir: &Path, path: &str) {
+    // /proc files report a size of 0, so they need to be read rather than copied
+    let Ok(data) = fs::read(path) else {
+        return;
+    };
+    let target = dir.join(path.trim_start_matches('/'));
+    if let Some(parent) = target.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    let _ = fs::write(target, data);
+}
+
+/// Recreates a symbolic link, such as /proc/[pid]/exe, in the snapshot directory
+fn copy_link(dir: &Path, path: &str) {
+    if let Ok(target) = fs::read_link(path) {
+        let _ = symlink(target, dir.join(path.trim_start_matches('/')));
+    }
+}
+
+/// Copies all files memory reads into dir; returns the number of processes
+fn capture_into(dir: &Path) -> io::Result<usize> {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    fs::write(dir.join(SNAPSHOT_TIMESTAMP_FILE), timestamp.to_string())?;
+    for path in SYSTEM_FILES {
+        copy(dir, path);
+    }
+    for system_dir in ["/sys/kernel/mm/ksm", SESSIONS_DIR] {
+        for entry in fs::read_dir(system_dir).into_iter().flatten().flatten() {
+            copy(dir, &entry.path().to_string_lossy());
+        }
+    }
+
+    let mut processes = 0;
+    let mut cgroups = HashSet::new();
+    for entry in fs::read_dir("/proc")?.flatten() {
+        let pid = entry.file_name().to_string_lossy().into_owned();
+        if !is_numeric_dir(&pid) {
+            continue;
+        }
+        // Processes vanish; capture whatever is left of them
+        for file in PROCESS_FILES {
+            copy(dir, &format!("/proc/{pid}/{file}"));
+        }
+        if !dir.join(format!("proc/{pid}/status")).exists() {
+            continue;
+        }
+        processes += 1;
+        copy_link(dir, &format!("/proc/{pid}/exe"));
+        // Names Flatpak apps that don't run in a scope of their own
+        copy(dir, &format!("/proc/{pid}/root/.flatpak-info"));
+        if let Some(cgroup) = cgroup::cgroup_dir(&pid).filter(|c| cgroups.insert(c.clone())) {
+            copy(dir, &cgroup.join("memory.stat").to_string_lossy());
+        }
+        // Only GPU file descriptors are of interest, with their memory in fdinfo
+        let _ = fs::create_dir_all(dir.join(format!("proc/{pid}/fd")));
+        for fd in fs::read_dir(format!("/proc/{pid}/fd")).into_iter().flatten().flatten() {
+            let fd = fd.file_name().to_string_lossy().into_owned();
+            let link = format!("/proc/{pid}/fd/{fd}");
+            if fs::read_link(&link).is_ok_and(|target| target.starts_with("/dev/dri/")) {
+                copy_link(dir, &link);
+                copy(dir, &format!("/proc/{pid}/fdinfo/{fd}"));
+            }
+        }
+    }
+    Ok(processes)
+}
+
+/// Creates a new directory only we can access. It gets a random name, and an
+/// existing one is never reused, so that other users can't prepare it for us.
+fn create_temp_dir(prefix: &str) -> io::Result<PathBuf> {
+    let mut random = [0; 8];
+    File::open("/dev/urandom")?.read_exact(&mut random)?;
+    let dir = std::env::temp_dir().join(format!("{prefix}-{:016x}", u64::from_ne_bytes(random)));
+    fs::DirBuilder::new().mode(0o700).create(&dir)?;
+    Ok(dir)
+}
+
+/// Captures the current state into an archive at output, compressed according
+/// to its extension (.tar.zst, .tar.gz, .tar.xz) by tar
+pub fn capture(out: &mut dyn Write, output: &str) -> io::Result<()> {
+    let dir = create_temp_dir("memory-capture")?;
+    let result = capture_into(&dir).and_then(|processes| {
+        let status = Command::new("tar").args(["-caf", output, "-C"]).arg(&dir).arg(".").status()?;
+        if !status.success() {
+            return Err(io::Error::other(format!("tar failed to write {output}")));
+        }
+        Ok(processes)
+    });
+    let _ = fs::remove_dir_all(&dir);
+    writeln!(out, "Captured {} processes to {output}", result?)
+}
+
+/// A snapshot opened with --from
+pub struct Snapshot {
+    pub root: PathBuf,
+    /// Whether root was extracted from an archive and needs to be removed again
+    pub extracted: bool,
+}
+
+impl Snapshot {
+    /// Opens an archive written by capture, or a directory it was extracted to
+    pub fn open(path: &str) -> Snapshot {
+        if Path::new(path).is_dir() {
+            return Snapshot { root: PathBuf::from(path), extracted: false };
+        }
+        let extracted = Snapshot::temporary().and_then(|snapshot| {
+            let status = Command::new("tar").arg("-xf").arg(path).arg("-C").arg(&snapshot.root).status()?;
+            if !status.success() {
+                return Err(io::Error::other("tar failed"));
+            }
+            Ok(snapshot)
+        });
+        extracted.unwrap_or_else(|e| {
+            eprintln!("Failed to extract snapshot {path}: {e}");
+            std::process::exit(1);
+        })
+    }
+
+    /// A snapshot in a new temporary directory, which is removed again when dropped
+    pub fn temporary() -> io::Result<Snapshot> {
+        Ok(Snapshot { root: create_temp_dir("memory-snapshot")?, extracted: true })
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        if self.extracted {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+}
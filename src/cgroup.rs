@@ -0,0 +1,141 @@
+//! Transient cgroups (v2) for measuring a command with the kernel's own memory
+//! accounting instead of sampling, and that accounting for the cgroups
+//! processes already run in.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::procfs;
+
+/// Set when memory has re-executed itself in a systemd scope, to not do so again
+pub const SCOPE_ENV: &str = "MEMORY_RUN_SCOPE";
+
+/// Where the cgroup v2 hierarchy is mounted; /sys/fs/cgroup/unified on hybrid systems
+fn mount_point() -> Option<PathBuf> {
+    let mounts = fs::read_to_string(procfs::path("/proc/self/mounts")).ok()?;
+    let line = mounts.lines().find(|l| l.split_whitespace().nth(2) == Some("cgroup2"))?;
+    line.split_whitespace().nth(1).map(PathBuf::from)
+}
+
+/// The cgroup v2 directory of a process, None on cgroup v1 only systems
+pub fn cgroup_dir(pid: &str) -> Option<PathBuf> {
+    let content = fs::read_to_string(format!("/proc/{pid}/cgroup")).ok()?;
+    let path = content.lines().find_map(|l| l.strip_prefix("0::"))?;
+    Some(mount_point()?.join(path.trim_start_matches('/')))
+}
+
+fn read_value(dir: &Path, file: &str) -> Option<u64> {
+    fs::read_to_string(dir.join(file)).ok()?.trim().parse().ok()
+}
+
+/// Reads a flat keyed file like memory.stat or memory.events
+pub fn read_keyed(dir: &Path, file: &str) -> HashMap<String, u64> {
+    let Ok(content) = fs::read_to_string(dir.join(file)) else {
+        return HashMap::new();
+    };
+    content
+        .lines()
+        .filter_map(|l| l.split_once(' '))
+        .filter_map(|(k, v)| Some((k.to_string(), v.parse().ok()?)))
+        .collect()
+}
+
+/// A cgroup's memory as the kernel accounts it in memory.stat, in kB
+#[derive(Clone, Copy)]
+pub struct MemoryStat {
+    pub anon_kb: u64,
+    /// The page cache, including tmpfs and shared memory
+    pub file_kb: u64,
+    /// All kernel memory, including slab_kb
+    pub kernel_kb: u64,
+    pub slab_kb: u64,
+}
+
+/// Reads memory.stat of a cgroup given as its path in the hierarchy, as in
+/// /proc/[pid]/cgroup. Snapshots don't contain the cgroup hierarchy, so there
+/// is none to read for them.
+pub fn read_memory_stat(cgroup: &str) -> Option<MemoryStat> {
+    let dir = procfs::path(&mount_point()?.join(cgroup.trim_start_matches('/')).to_string_lossy());
+    let stat = read_keyed(&dir, "memory.stat");
+    let get = |key: &str| stat.get(key).copied();
+    // The kernel total is only reported since Linux 5.18; sum its main parts before
+    let kernel = get("kernel").unwrap_or_else(|| ["kernel_stack", "pagetables", "percpu", "sock", "slab"].iter().filter_map(|k| get(k)).sum());
+    Some(MemoryStat { anon_kb: get("anon")? / 1024, file_kb: get("file")? / 1024, kernel_kb: kernel / 1024, slab_kb: get("slab")? / 1024 })
+}
+
+fn has_memory_controller(dir: &Path) -> bool {
+    fs::read_to_string(dir.join("cgroup.subtree_control")).is_ok_and(|c| c.split_whitespace().any(|c| c == "memory"))
+}
+
+/// The leaf we move into while the memory controller is enabled for the
+/// children of our own cgroup
+const SUPERVISOR: &str = "memory-supervisor";
+
+/// A cgroup created for one command, removed again when dropped
+pub struct TransientCgroup {
+    pub dir: PathBuf,
+    /// Our own cgroup, if we moved out of it to enable the memory controller
+    /// there; we move back and disable the controller again when dropped
+    moved_from: Option<PathBuf>,
+}
+
+impl TransientCgroup {
+    /// Creates a child of our own cgroup with memory accounting enabled. If the
+    /// memory controller isn't enabled for our children yet, that is only
+    /// possible if we are alone in our cgroup (e.g. in a delegated scope): we
+    /// then move ourselves into a leaf first, as cgroups with controllers
+    /// enabled for their children can't contain processes.
+    pub fn create() -> io::Result<TransientCgroup> {
+        let own = cgroup_dir("self").ok_or_else(|| io::Error::other("cgroup v2 is not mounted"))?;
+        let controllers = fs::read_to_string(own.join("cgroup.controllers"))?;
+        if !controllers.split_whitespace().any(|c| c == "memory") {
+            return Err(io::Error::other("the memory controller is not available in cgroup v2"));
+        }
+        let mut moved_from = None;
+        if !has_memory_controller(&own) {
+            let supervisor = own.join(SUPERVISOR);
+            fs::create_dir_all(&supervisor)?;
+            fs::write(supervisor.join("cgroup.procs"), "0")?;
+            if let Err(e) = fs::write(own.join("cgroup.subtree_control"), "+memory") {
+                let _ = fs::write(own.join("cgroup.procs"), "0");
+                let _ = fs::remove_dir(&supervisor);
+                return Err(e);
+            }
+            moved_from = Some(own.clone());
+        }
+        // Dropped, and so undone, if this fails
+        let cgroup = TransientCgroup { dir: own.join(format!("memory-run-{}", std::process::id())), moved_from };
+        fs::create_dir(&cgroup.dir)?;
+        Ok(cgroup)
+    }
+
+    /// cgroup.procs opened for writing; writing "0" moves the writing process in
+    pub fn procs(&self) -> io::Result<File> {
+        File::options().write(true).open(self.dir.join("cgroup.procs"))
+    }
+
+    /// memory.current in bytes
+    pub fn current(&self) -> Option<u64> {
+        read_value(&self.dir, "memory.current")
+    }
+
+    /// memory.peak in bytes, available since Linux 5.19
+    pub fn peak(&self) -> Option<u64> {
+        read_value(&self.dir, "memory.peak")
+    }
+}
+
+impl Drop for TransientCgroup {
+    fn drop(&mut self) {
+        // Fails if the command left processes behind; they keep the cgroup alive
+        let _ = fs::remove_dir(&self.dir);
+        if let Some(own) = &self.moved_from {
+            // Processes may only return once no controllers are enabled for children
+            let _ = fs::write(own.join("cgroup.subtree_control"), "-memory");
+            let _ = fs::write(own.join("cgroup.procs"), "0");
+            let _ = fs::remove_dir(own.join(SUPERVISOR));
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! Runs external programs that name processes, for site-specific rules that
+//! are too complex for aliases. The program reads one JSON record per process
+//! and line on stdin, and writes one line with the group name for each record
+//! to stdout; an empty line keeps the name memory derived.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+
+use crate::json::{self, Value};
+use crate::model::ProcessSample;
+use crate::procfs::{exe_path, read_cgroup, read_cmdline, read_comm, read_environ};
+
+/// Names a classifier returned earlier, by pid and the name the process had
+/// when it was passed in
+pub type Memo = HashMap<(u32, String), String>;
+
+/// The metadata a classifier can base its decision on
+fn record(p: &ProcessSample) -> Value {
+    let pid = p.pid.to_string();
+    let cmdline = read_cmdline(&pid).unwrap_or_default();
+    let environ = read_environ(&pid).unwrap_or_default();
+    json::object([
+        ("pid", p.pid.into()),
+        ("ppid", p.ppid.into()),
+        ("uid", p.uid.into()),
+        ("user", p.user.as_str().into()),
+        ("name", p.name.as_str().into()),
+        ("comm", read_comm(&pid).into()),
+        ("exe", exe_path(&pid).into()),
+        ("cmdline", Value::Array(cmdline.into_iter().map(Value::from).collect())),
+        ("cgroup", read_cgroup(&pid).into()),
+        ("environ", Value::Object(environ.into_iter().map(|(k, v)| (k, v.into())).collect())),
+    ])
+}
+
+/// Passes the samples through the classifier started by command, renaming
+/// those it returns a name for
+pub fn classify(mut command: Command, samples: &mut [&mut ProcessSample]) -> io::Result<()> {
+    if samples.is_empty() {
+        return Ok(());
+    }
+    let mut child = command.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+    let mut stdin = child.stdin.take().expect("piped");
+    let stdout = child.stdout.take().expect("piped");
+    let records: Vec<String> = samples.iter().map(|p| record(p).to_compact_string()).collect();
+    // Write from another thread, so that neither side blocks on a full pipe
+    let names = std::thread::scope(|scope| {
+        scope.spawn(move || {
+            for line in records {
+                if writeln!(stdin, "{line}").is_err() {
+                    break;
+                }
+            }
+        });
+        BufReader::new(stdout).lines().collect::<io::Result<Vec<String>>>()
+    })?;
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("exited with {status}")));
+    }
+    if names.len() != samples.len() {
+        return Err(io::Error::other(format!("returned {} names for {} processes", names.len(), samples.len())));
+    }
+    for (p, name) in samples.iter_mut().zip(names) {
+        let name = name.trim();
+        if !name.is_empty() {
+            p.name = name.to_string();
+        }
+    }
+    Ok(())
+}
+
+/// Like classify, but only passes processes that weren't classified before;
+/// the others get the name they were given then. memo is updated to the
+/// current processes.
+pub fn classify_memoized(command: Command, samples: &mut [&mut ProcessSample], memo: &mut Memo) -> io::Result<()> {
+    let keys: Vec<(u32, String)> = samples.iter().map(|p| (p.pid, p.name.clone())).collect();
+    let mut new = vec![];
+    for (p, key) in samples.iter_mut().zip(&keys) {
+        match memo.get(key) {
+            Some(name) => p.name = name.clone(),
+            None => new.push(&mut **p),
+        }
+    }
+    classify(command, &mut new)?;
+    *memo = keys.into_iter().zip(samples.iter()).map(|(key, p)| (key, p.name.clone())).collect();
+    Ok(())
+}
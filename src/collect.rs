@@ -0,0 +1,186 @@
+//! Scans /proc for running processes.
+
+use std::collections::HashMap;
+#[cfg(not(any(target_os = "freebsd", windows)))]
+use std::fs;
+
+use crate::classify;
+use crate::naming::{JavaStrategy, NameSource};
+#[cfg(not(any(target_os = "freebsd", windows)))]
+use crate::{
+    cgroup::{self, MemoryStat},
+    gpu,
+    model::ProcessSample,
+    naming::process_key,
+    numa::read_numa_maps,
+    procfs::{self, ProcessFiles, is_numeric_dir, read_user_names},
+    sandbox::container_name,
+    smaps,
+};
+
+/// Controls how processes are named and which optional details are sampled
+pub struct Config {
+    pub name_from: NameSource,
+    pub java: JavaStrategy,
+    /// Read smaps to split memory up by mapping type; this is comparatively slow
+    pub breakdown: bool,
+    /// Read smaps_rollup for dirty page totals
+    pub dirty: bool,
+    /// Read numa_maps for memory per NUMA node
+    pub numa: bool,
+    /// Read DRM fdinfo and query nvidia-smi for GPU memory
+    pub gpu: bool,
+    /// Read smaps_rollup for PSS and USS
+    pub metrics: bool,
+    /// Read each process's start time
+    pub age: bool,
+    /// Read memory.stat of the cgroup each process runs in
+    pub cgroup_memory: bool,
+    /// Keep processes that are entirely swapped out, which are otherwise
+    /// skipped along with kernel threads
+    pub swap: bool,
+    /// Keep processes whose memory is all in hugetlbfs pages
+    pub hugetlb: bool,
+}
+
+/// Per-process state kept between the scans of the continuous modes, so that
+/// processes which keep running are only named once. Only the status fields
+/// and the optional details are read again on each refresh.
+#[derive(Default)]
+pub struct Cache {
+    /// Login names by uid, read on the first scan
+    users: Option<HashMap<u32, String>>,
+    processes: HashMap<u32, CachedProcess>,
+    /// Names given by each classifier command and plugin
+    pub classified: HashMap<String, classify::Memo>,
+}
+
+/// A process's name and what it was derived from. The command name changes
+/// when the process exec()s another program, and usually when its pid is
+/// reused; the command line is only kept if naming needed it.
+struct CachedProcess {
+    comm: String,
+    cmdline: Option<Vec<String>>,
+    name: String,
+    container: Option<String>,
+}
+
+#[cfg(target_os = "freebsd")]
+pub use crate::freebsd::collect_processes;
+#[cfg(windows)]
+pub use crate::windows::collect_processes;
+
+#[cfg(not(any(target_os = "freebsd", windows)))]
+pub fn collect_processes(config: &Config, cache: &mut Cache) -> Vec<ProcessSample> {
+    let proc = match fs::read_dir(procfs::path("/proc")) {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("Failed to read /proc: {e}");
+            procfs::exit(1);
+        }
+    };
+
+    let users = cache.users.get_or_insert_with(read_user_names);
+    let mut running = HashMap::new();
+    let nvidia = if config.gpu { gpu::nvidia_memory() } else { Default::default() };
+    let boot_time = if config.age { procfs::read_boot_time() } else { None };
+    // Processes share cgroups, whose memory.stat is read once per scan
+    let mut memory_stats: HashMap<String, Option<MemoryStat>> = HashMap::new();
+    let mut processes = Vec::new();
+    for entry in proc.flatten() {
+        let name = match entry.file_name().into_string() {
+            Ok(s) => s,
+            Err(_) => continue,
+        };
+        if !is_numeric_dir(&name) {
+            continue;
+        }
+
+        // Processes vanish; ignore errors quietly.
+        let files = ProcessFiles::new(&name);
+        let status = match files.status() {
+            Some(v) => v,
+            None => continue,
+        };
+        let swapped = config.swap && status.swap_kb > 0;
+        let huge = config.hugetlb && status.hugetlb_kb > 0;
+        if status.rss_kb == 0 && !swapped && !huge {
+            continue;
+        }
+
+        let pid = name.parse().unwrap_or(0);
+        // Escaped names differ from the comm file
+        if !status.name.contains('\\') {
+            files.set_comm(status.name.clone());
+        }
+        // Processes that vanished since the last scan drop out of the cache
+        let cached = cache.processes.remove(&pid).filter(|c| {
+            c.comm == status.name && c.cmdline.as_deref().is_none_or(|cmdline| files.cmdline() == Some(cmdline))
+        });
+        let (key, container) = match cached {
+            Some(cached) => (cached.name, cached.container),
+            None => match process_key(&files, config.name_from, config.java) {
+                Some(k) => (k, container_name(&files)),
+                None => continue,
+            },
+        };
+        let cmdline = files.cmdline_if_read().map(<[String]>::to_vec);
+        running.insert(pid, CachedProcess { comm: status.name, cmdline, name: key.clone(), container: container.clone() });
+
+        let breakdown = if config.breakdown {
+            smaps::read_smaps(&name).map(|m| smaps::breakdown(&m))
+        } else {
+            None
+        };
+
+        // Read once for whichever of its values were requested
+        let rollup = if config.dirty || config.metrics { files.open("smaps_rollup").and_then(smaps::parse_rollup) } else { None };
+        let dirty = rollup.as_ref().filter(|_| config.dirty).map(|r| r.dirty);
+        let pss_kb = rollup.as_ref().filter(|_| config.metrics).map(|r| r.pss_kb);
+        let uss_kb = rollup.as_ref().filter(|_| config.metrics).map(|r| r.uss_kb());
+
+        let numa = if config.numa { read_numa_maps(&name) } else { None };
+
+        let gpu_kb = if config.gpu {
+            match (gpu::read_drm_memory(&name), nvidia.get(&pid)) {
+                (None, None) => None,
+                (drm, nv) => Some(drm.unwrap_or(0) + nv.copied().unwrap_or(0)),
+            }
+        } else {
+            None
+        };
+
+        let start_time = boot_time.and_then(|b| files.start_time(b));
+
+        let cgroup = if config.cgroup_memory { files.cgroup().map(str::to_string) } else { None };
+        let cgroup_memory = cgroup.as_ref().and_then(|c| *memory_stats.entry(c.clone()).or_insert_with(|| cgroup::read_memory_stat(c)));
+
+        processes.push(ProcessSample {
+            pid,
+            ppid: status.ppid,
+            sid: status.sid,
+            uid: status.uid,
+            user: users.get(&status.uid).cloned().unwrap_or_else(|| status.uid.to_string()),
+            name: key,
+            rss_kb: status.rss_kb,
+            hugetlb_kb: status.hugetlb_kb,
+            locked_kb: status.locked_kb,
+            hwm_kb: status.hwm_kb,
+            peak_kb: status.peak_kb,
+            swap_kb: status.swap_kb,
+            threads: status.threads,
+            gpu_kb,
+            pss_kb,
+            uss_kb,
+            start_time,
+            breakdown,
+            dirty,
+            numa,
+            cgroup,
+            cgroup_memory,
+            container,
+        });
+    }
+    cache.processes = running;
+    processes
+}
@@ -0,0 +1,213 @@
+//! The `compare` subcommand: compares memory usage against a baseline saved
+//! with `--format=json`, for use as a regression gate in CI pipelines.
+
+use std::fs;
+use std::io::{self, Write};
+
+use crate::json::{self, Value};
+use crate::model::Report;
+use crate::procfs;
+
+/// Exit code when a measurement grew by more than allowed
+pub const REGRESSION_EXIT_CODE: i32 = 3;
+
+/// Named sizes in kB taken from a report or a `memory run` measurement
+fn measurements(value: &Value) -> Result<Vec<(String, f64)>, String> {
+    let kb = |v: &Value, key: &str| v.get(key).and_then(Value::as_f64);
+    if let Some(groups) = value.get("groups").and_then(Value::as_array) {
+        let mut result = vec![];
+        for group in groups {
+            let name = group.get("name").and_then(Value::as_str).ok_or("group without a name")?;
+            let rss_kb = kb(group, "rss_kb").ok_or_else(|| format!("group {name} without rss_kb"))?;
+            result.push((name.to_string(), rss_kb));
+        }
+        // Reports written before total_rss_kb existed only have the listed groups
+        let total = kb(value, "total_rss_kb").unwrap_or_else(|| result.iter().map(|(_, kb)| kb).sum());
+        result.insert(0, ("Total".to_string(), total));
+        return Ok(result);
+    }
+    let Value::Object(fields) = value else {
+        return Err("expected a JSON object".to_string());
+    };
+    // A run measurement: rss_kb, pss_kb or memory_kb, each with peak and average
+    let mut result = vec![];
+    for (key, stats) in fields {
+        let Some(name) = key.strip_suffix("_kb") else {
+            continue;
+        };
+        for stat in ["peak", "average"] {
+            if let Some(kb) = kb(stats, stat) {
+                result.push((format!("{} {stat}", name.to_uppercase()), kb));
+            }
+        }
+    }
+    if result.is_empty() {
+        return Err("neither a report nor a run measurement".to_string());
+    }
+    Ok(result)
+}
+
+/// Whether a report lists only its largest groups, as `--format=json` does
+/// unless given a higher limit; groups missing from it may still have run
+fn is_truncated(value: &Value) -> bool {
+    let Some(total) = value.get("total_rss_kb").and_then(Value::as_f64) else {
+        return false;
+    };
+    let groups = value.get("groups").and_then(Value::as_array).unwrap_or_default();
+    let listed: f64 = groups.iter().filter_map(|g| g.get("rss_kb").and_then(Value::as_f64)).sum();
+    listed < total
+}
+
+/// What a baseline or current file holds
+struct Measurements {
+    sizes: Vec<(String, f64)>,
+    /// From a report rather than a `memory run` measurement
+    report: bool,
+    truncated: bool,
+}
+
+impl Measurements {
+    fn from_json(value: &Value) -> Result<Measurements, String> {
+        Ok(Measurements { sizes: measurements(value)?, report: value.get("groups").is_some(), truncated: is_truncated(value) })
+    }
+
+    fn kind(&self) -> &'static str {
+        if self.report { "a report" } else { "a run measurement" }
+    }
+}
+
+fn read_measurements(path: &str) -> Measurements {
+    let parsed = fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| json::parse(&text))
+        .and_then(|value| Measurements::from_json(&value));
+    parsed.unwrap_or_else(|e| {
+        eprintln!("Failed to read {path}: {e}");
+        procfs::exit(1);
+    })
+}
+
+/// Compares the measurement in the current file, or a fresh report, against the
+/// baseline. Returns REGRESSION_EXIT_CODE if anything grew by more than
+/// max_growth percent.
+pub fn compare(
+    out: &mut dyn Write,
+    baseline: &str,
+    current: Option<&str>,
+    build: impl FnOnce() -> Report,
+    max_growth: f64,
+) -> io::Result<i32> {
+    let baseline = read_measurements(baseline);
+    let current = match current {
+        Some(path) => read_measurements(path),
+        None => Measurements::from_json(&build().to_json(usize::MAX)).unwrap_or_else(|e| {
+            eprintln!("Failed to measure: {e}");
+            procfs::exit(1);
+        }),
+    };
+    // Otherwise every measurement would be gone and the gate would pass
+    if baseline.report != current.report {
+        eprintln!("The baseline is {} but the current measurement is {}", baseline.kind(), current.kind());
+        procfs::exit(1);
+    }
+    if !baseline.sizes.iter().any(|(name, _)| current.sizes.iter().any(|(n, _)| n == name)) {
+        eprintln!("None of the baseline's measurements were found in the current one");
+        procfs::exit(1);
+    }
+    let (baseline_truncated, current_truncated) = (baseline.truncated, current.truncated);
+    let (baseline, current) = (baseline.sizes, current.sizes);
+
+    writeln!(out, "{:<35} {:>13} {:>13} {:>9}", "Measurement", "Baseline(MB)", "Current(MB)", "Change")?;
+    let mut exceeded = 0;
+    for (name, base_kb) in &baseline {
+        let Some((_, kb)) = current.iter().find(|(n, _)| n == name) else {
+            // In a truncated report, the group may just have become smaller
+            if current_truncated {
+                continue;
+            }
+            writeln!(out, "{:<35} {:>13.2} {:>13} {:>9}  gone", name, base_kb / 1024.0, "", "")?;
+            continue;
+        };
+        let growth = if *base_kb > 0.0 {
+            (kb - base_kb) * 100.0 / base_kb
+        } else if *kb > 0.0 {
+            // Any growth from nothing is too much
+            f64::INFINITY
+        } else {
+            0.0
+        };
+        let status = if growth > max_growth {
+            exceeded += 1;
+            format!("  exceeds {max_growth}%")
+        } else {
+            String::new()
+        };
+        writeln!(out, "{:<35} {:>13.2} {:>13.2} {:>+8.2}%{status}", name, base_kb / 1024.0, kb / 1024.0, growth)?;
+    }
+    // Groups missing from a truncated baseline were there, only smaller
+    let new = current.iter().filter(|(n, _)| !baseline_truncated && !baseline.iter().any(|(b, _)| b == n));
+    for (name, kb) in new {
+        writeln!(out, "{:<35} {:>13} {:>13.2} {:>9}  new", name, "", kb / 1024.0, "")?;
+    }
+
+    writeln!(out)?;
+    if exceeded > 0 {
+        writeln!(out, "{exceeded} of {} measurements grew by more than {max_growth}%", baseline.len())?;
+        Ok(REGRESSION_EXIT_CODE)
+    } else {
+        writeln!(out, "No measurement grew by more than {max_growth}%")?;
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sizes(text: &str) -> Result<Vec<(String, f64)>, String> {
+        measurements(&json::parse(text).unwrap())
+    }
+
+    fn named(pairs: &[(&str, f64)]) -> Vec<(String, f64)> {
+        pairs.iter().map(|(name, kb)| (name.to_string(), *kb)).collect()
+    }
+
+    #[test]
+    fn reads_reports() {
+        let report = r#"{"schema_version": 1, "total_rss_kb": 1000, "groups": [{"name": "firefox", "rss_kb": 600}, {"name": "bash", "rss_kb": 100}]}"#;
+        assert_eq!(sizes(report).unwrap(), named(&[("Total", 1000.0), ("firefox", 600.0), ("bash", 100.0)]));
+        assert!(is_truncated(&json::parse(report).unwrap()));
+    }
+
+    #[test]
+    fn sums_the_total_of_old_reports() {
+        let report = r#"{"groups": [{"name": "a", "rss_kb": 3}, {"name": "b", "rss_kb": 4}]}"#;
+        assert_eq!(sizes(report).unwrap(), named(&[("Total", 7.0), ("a", 3.0), ("b", 4.0)]));
+        assert!(!is_truncated(&json::parse(report).unwrap()));
+        assert_eq!(sizes(r#"{"groups": []}"#).unwrap(), named(&[("Total", 0.0)]));
+    }
+
+    #[test]
+    fn reads_run_measurements() {
+        let run = r#"{"schema_version": 1, "command": ["sleep", "1"], "elapsed_s": 1.0, "samples": 10,
+            "rss_kb": {"peak": 1544, "average": 1500.5}, "pss_kb": {"peak": 443, "average": 400}, "memory_kb": {"peak": 2048}}"#;
+        let expected = [("RSS peak", 1544.0), ("RSS average", 1500.5), ("PSS peak", 443.0), ("PSS average", 400.0), ("MEMORY peak", 2048.0)];
+        assert_eq!(sizes(run).unwrap(), named(&expected));
+    }
+
+    #[test]
+    fn tells_reports_and_runs_apart() {
+        let report = Measurements::from_json(&json::parse(r#"{"groups": []}"#).unwrap()).unwrap();
+        let run = Measurements::from_json(&json::parse(r#"{"rss_kb": {"peak": 1}}"#).unwrap()).unwrap();
+        assert_eq!((report.kind(), run.kind()), ("a report", "a run measurement"));
+    }
+
+    #[test]
+    fn rejects_other_documents() {
+        assert!(sizes("[]").is_err());
+        assert!(sizes(r#"{"elapsed_s": 1}"#).is_err());
+        assert!(sizes(r#"{"rss_kb": 5}"#).is_err());
+        assert_eq!(sizes(r#"{"groups": [{"rss_kb": 1}]}"#).unwrap_err(), "group without a name");
+        assert_eq!(sizes(r#"{"groups": [{"name": "a"}]}"#).unwrap_err(), "group a without rss_kb");
+    }
+}
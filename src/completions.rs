@@ -0,0 +1,173 @@
+//! The `completions` subcommand, which prints a completion script for bash,
+//! zsh or fish. The scripts complete group names by running
+//! `memory completions groups`.
+
+use std::io::{self, Write};
+
+use crate::model::Report;
+
+pub const SHELLS: [&str; 3] = ["bash", "zsh", "fish"];
+
+const SUBCOMMANDS: [&str; 16] = [
+    "libs", "files", "ksm", "shm", "tmpfs", "swap", "cached", "oom", "pid", "top", "run", "follow", "compare", "blame", "capture", "completions",
+];
+
+/// Subcommands whose argument is a group
+const GROUP_SUBCOMMANDS: [&str; 2] = ["files", "follow"];
+
+/// Options without a value
+const FLAGS: [&str; 16] = ["metrics", "threads", "breakdown", "cgroup-memory", "hugepages", "locked", "dirty", "peak", "numa", "gpu", "age", "bars", "watch", "merge-hosts", "adb", "cgroup"];
+
+/// Options taking one of a fixed set of values, as --option=value
+const CHOICES: [(&str, &[&str]); 8] = [
+    ("name-from", &["auto", "cmdline", "exe", "comm", "path"]),
+    ("java-by", &["auto", "jar", "main"]),
+    ("rollup", &["session", "launcher"]),
+    ("by", &["name", "session"]),
+    ("format", &["table", "markdown", "json", "treemap", "csv"]),
+    ("sort", &["memory", "locked", "hwm", "peak", "swap", "gpu", "pss", "uss", "threads"]),
+    ("bars", &["largest", "total"]),
+    ("nest", &["user"]),
+];
+
+/// Options taking a file name, as --option FILE or --option=FILE
+const FILE_OPTIONS: [&str; 4] = ["output", "from", "baseline", "plugin"];
+
+/// Options taking another value, as --option VALUE or --option=VALUE
+const VALUE_OPTIONS: [&str; 6] = ["alias", "by-env", "classify-cmd", "for", "host", "max-growth"];
+
+/// Options only taking a value as --option=VALUE
+const EQUALS_OPTIONS: [&str; 4] = ["adb", "remote-command", "wasm-runtime", "watch"];
+
+/// All options that end in =, to be completed without a trailing space
+fn equals_options() -> Vec<String> {
+    let options = CHOICES.iter().map(|(option, _)| *option).chain(FILE_OPTIONS).chain(VALUE_OPTIONS).chain(EQUALS_OPTIONS);
+    options.map(|o| format!("--{o}=")).collect()
+}
+
+fn flags() -> Vec<String> {
+    FLAGS.iter().map(|f| format!("--{f}")).collect()
+}
+
+/// Options followed by their value as the next argument
+fn separate_value_options(files: bool) -> String {
+    let options = if files { &FILE_OPTIONS[..] } else { &VALUE_OPTIONS[..] };
+    let mut patterns: Vec<String> = options.iter().filter(|o| **o != "host").map(|o| format!("--{o}")).collect();
+    if files {
+        patterns.insert(0, "-o".to_string());
+    }
+    patterns.join("|")
+}
+
+fn bash(out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, "# bash completion for memory, generated by `memory completions bash`")?;
+    writeln!(out, "_memory() {{")?;
+    writeln!(out, "    local cur=${{COMP_WORDS[COMP_CWORD]}} prev=${{COMP_WORDS[COMP_CWORD-1]}} option= IFS=$'\\n'")?;
+    writeln!(out, "    # With the default COMP_WORDBREAKS, the = of --option=value is a word of its own")?;
+    writeln!(out, "    if [[ $cur == = ]]; then")?;
+    writeln!(out, "        option=$prev cur=")?;
+    writeln!(out, "    elif [[ $prev == = ]]; then")?;
+    writeln!(out, "        option=${{COMP_WORDS[COMP_CWORD-2]}}")?;
+    writeln!(out, "    fi")?;
+    writeln!(out, "    if [[ -n $option ]]; then")?;
+    writeln!(out, "        case $option in")?;
+    for (option, values) in CHOICES {
+        writeln!(out, "            --{option}) COMPREPLY=($(compgen -W $'{}' -- \"$cur\")) ;;", values.join("\\n"))?;
+    }
+    writeln!(out, "            {}) COMPREPLY=($(compgen -f -- \"$cur\")) ;;", separate_value_options(true).replace("-o|", ""))?;
+    writeln!(out, "            --host) COMPREPLY=($(compgen -A hostname -- \"$cur\")) ;;")?;
+    writeln!(out, "        esac")?;
+    writeln!(out, "        return")?;
+    writeln!(out, "    fi")?;
+    writeln!(out, "    case $prev in")?;
+    writeln!(out, "        {}) COMPREPLY=($(compgen -W \"$(memory completions groups 2>/dev/null)\" -- \"$cur\")); return ;;", GROUP_SUBCOMMANDS.join("|"))?;
+    writeln!(out, "        {}) COMPREPLY=($(compgen -f -- \"$cur\")); return ;;", separate_value_options(true))?;
+    writeln!(out, "        --host) COMPREPLY=($(compgen -A hostname -- \"$cur\")); return ;;")?;
+    writeln!(out, "        completions) COMPREPLY=($(compgen -W $'{}' -- \"$cur\")); return ;;", SHELLS.join("\\n"))?;
+    writeln!(out, "        {}) return ;;", separate_value_options(false))?;
+    writeln!(out, "    esac")?;
+    writeln!(out, "    if [[ $cur == -* ]]; then")?;
+    let options: Vec<String> = flags().into_iter().chain(equals_options()).collect();
+    writeln!(out, "        COMPREPLY=($(compgen -W $'{}' -- \"$cur\"))", options.join("\\n"))?;
+    writeln!(out, "        [[ ${{COMPREPLY[0]}} == *= ]] && compopt -o nospace")?;
+    writeln!(out, "    else")?;
+    writeln!(out, "        COMPREPLY=($(compgen -W $'{}' -- \"$cur\"))", SUBCOMMANDS.join("\\n"))?;
+    writeln!(out, "    fi")?;
+    writeln!(out, "}}")?;
+    writeln!(out, "complete -F _memory memory")
+}
+
+fn zsh(out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, "#compdef memory")?;
+    writeln!(out, "# zsh completion for memory, generated by `memory completions zsh`")?;
+    writeln!(out, "_memory() {{")?;
+    writeln!(out, "    case $words[CURRENT-1] in")?;
+    writeln!(out, "        {}) compadd -- ${{(f)\"$(memory completions groups 2>/dev/null)\"}}; return ;;", GROUP_SUBCOMMANDS.join("|"))?;
+    writeln!(out, "        {}) _files; return ;;", separate_value_options(true))?;
+    writeln!(out, "        --host) _hosts; return ;;")?;
+    writeln!(out, "        completions) compadd {}; return ;;", SHELLS.join(" "))?;
+    writeln!(out, "        {}) return ;;", separate_value_options(false))?;
+    writeln!(out, "    esac")?;
+    writeln!(out, "    case $PREFIX in")?;
+    for (option, values) in CHOICES {
+        writeln!(out, "        --{option}=*) compset -P '*='; compadd {} ;;", values.join(" "))?;
+    }
+    let file_patterns: Vec<String> = FILE_OPTIONS.iter().map(|o| format!("--{o}=*")).collect();
+    writeln!(out, "        {}) compset -P '*='; _files ;;", file_patterns.join("|"))?;
+    writeln!(out, "        --host=*) compset -P '*='; _hosts ;;")?;
+    writeln!(out, "        -*) compadd -- {}; compadd -S '' -- {} ;;", flags().join(" "), equals_options().join(" "))?;
+    writeln!(out, "        *) compadd {} ;;", SUBCOMMANDS.join(" "))?;
+    writeln!(out, "    esac")?;
+    writeln!(out, "}}")?;
+    writeln!(out, "# Run when autoloaded from $fpath, registered when sourced")?;
+    writeln!(out, "if [[ $zsh_eval_context[-1] == loadautofunc ]]; then")?;
+    writeln!(out, "    _memory \"$@\"")?;
+    writeln!(out, "else")?;
+    writeln!(out, "    compdef _memory memory")?;
+    writeln!(out, "fi")
+}
+
+fn fish(out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, "# fish completion for memory, generated by `memory completions fish`")?;
+    writeln!(out, "complete -c memory -f")?;
+    writeln!(out, "complete -c memory -n __fish_use_subcommand -a '{}'", SUBCOMMANDS.join(" "))?;
+    writeln!(
+        out,
+        "complete -c memory -n '__fish_seen_subcommand_from {}' -a '(memory completions groups 2>/dev/null)'",
+        GROUP_SUBCOMMANDS.join(" ")
+    )?;
+    writeln!(out, "complete -c memory -n '__fish_seen_subcommand_from completions' -a '{}'", SHELLS.join(" "))?;
+    // --bars takes an optional value, which fish can't express; it is listed with the choices
+    for flag in FLAGS.iter().filter(|f| **f != "bars") {
+        writeln!(out, "complete -c memory -l {flag}")?;
+    }
+    for (option, values) in CHOICES {
+        writeln!(out, "complete -c memory -l {option} -x -a '{}'", values.join(" "))?;
+    }
+    writeln!(out, "complete -c memory -s o -r -F")?;
+    for option in FILE_OPTIONS {
+        writeln!(out, "complete -c memory -l {option} -r -F")?;
+    }
+    writeln!(out, "complete -c memory -l host -x -a '(__fish_print_hostnames)'")?;
+    for option in VALUE_OPTIONS.iter().chain(&EQUALS_OPTIONS).filter(|o| **o != "host" && **o != "watch") {
+        writeln!(out, "complete -c memory -l {option} -x")?;
+    }
+    Ok(())
+}
+
+/// Prints the completion script for shell, one of SHELLS
+pub fn print_completions(out: &mut dyn Write, shell: &str) -> io::Result<()> {
+    match shell {
+        "bash" => bash(out),
+        "zsh" => zsh(out),
+        _ => fish(out),
+    }
+}
+
+/// Lists the current groups for completing group names
+pub fn print_groups(out: &mut dyn Write, report: &Report) -> io::Result<()> {
+    for group in &report.groups {
+        writeln!(out, "{}", group.name)?;
+    }
+    Ok(())
+}
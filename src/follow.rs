@@ -0,0 +1,49 @@
+//! The `follow` subcommand: a time series of the memory used by the groups
+//! matching a pattern, for watching one suspect application over time.
+
+use std::io::{self, Write};
+use std::time::Duration;
+
+use crate::model::Report;
+use crate::pattern::Pattern;
+use crate::sys;
+
+/// Samples the matching groups every interval until interrupted, printing one
+/// line per sample as a table or as CSV
+pub fn follow(out: &mut dyn Write, pattern: &Pattern, mut build: impl FnMut() -> Report, interval: Duration, csv: bool) -> io::Result<()> {
+    if csv {
+        writeln!(out, "timestamp,rss_kb,swap_kb,processes,delta_kb")?;
+    } else {
+        writeln!(out, "{:<19} {:>12} {:>10} {:>5} {:>11}", "Time", "Memory(MB)", "Swap(MB)", "Num", "Delta(MB)")?;
+    }
+    let mut previous = None;
+    loop {
+        let report = build();
+        let groups = report.groups.iter().filter(|g| pattern.is_match(&g.name));
+        let (mut rss_kb, mut swap_kb, mut processes) = (0, 0, 0);
+        for group in groups {
+            rss_kb += group.rss_kb;
+            swap_kb += group.swap_kb;
+            processes += group.num();
+        }
+        let delta_kb = rss_kb as i64 - previous.unwrap_or(rss_kb) as i64;
+        previous = Some(rss_kb);
+
+        if csv {
+            writeln!(out, "{},{rss_kb},{swap_kb},{processes},{delta_kb}", report.timestamp)?;
+        } else {
+            writeln!(
+                out,
+                "{:<19} {:>12.2} {:>10.2} {:>5} {:>+11.2}",
+                sys::format_local_time(report.timestamp),
+                rss_kb as f64 / 1024.0,
+                swap_kb as f64 / 1024.0,
+                processes,
+                delta_kb as f64 / 1024.0
+            )?;
+        }
+        // Lines should show up right away when piped or written to a file
+        out.flush()?;
+        std::thread::sleep(interval);
+    }
+}
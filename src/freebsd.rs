@@ -0,0 +1,245 @@
+//! FreeBSD support for the grouped report. FreeBSD doesn't mount /proc by
+//! default, so processes are read with sysctl(3) instead. Only the memory
+//! totals kinfo_proc has are available; the detail columns stay empty.
+
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int, c_uint, c_void};
+
+use crate::collect::{Cache, Config};
+use crate::model::ProcessSample;
+use crate::naming::process_key;
+use crate::procfs::{ProcessFiles, read_user_names};
+
+const CTL_KERN: c_int = 1;
+const KERN_PROC: c_int = 14;
+const KERN_PROC_ARGS: c_int = 7;
+const KERN_PROC_PATHNAME: c_int = 12;
+const KERN_PROC_PID: c_int = 1;
+const KERN_PROC_PROC: c_int = 8;
+
+/// The leading fields of struct kinfo_proc from <sys/user.h> on 64-bit
+/// platforms, up to the thread count. The full structure is
+/// ki_structsize bytes long.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct KinfoProc {
+    ki_structsize: c_int,
+    ki_layout: c_int,
+    /// ki_args to ki_wchan
+    ki_pointers: [*const c_void; 8],
+    ki_pid: i32,
+    ki_ppid: i32,
+    ki_pgid: i32,
+    ki_tpgid: i32,
+    ki_sid: i32,
+    ki_tsid: i32,
+    ki_jobc: i16,
+    ki_spare_short1: i16,
+    ki_tdev_freebsd11: u32,
+    /// ki_siglist, ki_sigmask, ki_sigignore, ki_sigcatch
+    ki_sigsets: [[u32; 4]; 4],
+    ki_uid: u32,
+    ki_ruid: u32,
+    ki_svuid: u32,
+    ki_rgid: u32,
+    ki_svgid: u32,
+    ki_ngroups: i16,
+    ki_spare_short2: i16,
+    ki_groups: [u32; 16],
+    /// Virtual size in bytes
+    ki_size: u64,
+    /// Resident set size in pages
+    ki_rssize: i64,
+    /// ki_swrss, ki_tsize, ki_dsize, ki_ssize
+    ki_sizes: [i64; 4],
+    ki_xstat: u16,
+    ki_acflag: u16,
+    ki_pctcpu: u32,
+    ki_estcpu: u32,
+    ki_slptime: u32,
+    ki_swtime: u32,
+    ki_cow: u32,
+    ki_runtime: u64,
+    /// struct timeval: seconds and microseconds since the Unix epoch
+    ki_start: [i64; 2],
+    ki_childtime: [i64; 2],
+    ki_flag: i64,
+    ki_kiflag: i64,
+    ki_traceflag: c_int,
+    /// ki_stat, ki_nice, ki_lock, ki_rqindex, ki_oncpu_old, ki_lastcpu_old
+    ki_chars: [u8; 6],
+    /// ki_tdname, ki_wmesg, ki_login, ki_lockname
+    ki_names: [u8; 53],
+    /// The command name; COMMLEN plus the terminating null byte
+    ki_comm: [u8; 20],
+    /// ki_emul, ki_loginclass, ki_moretdname, ki_sparestrings
+    ki_more_names: [u8; 85],
+    ki_spareints: [c_int; 2],
+    ki_tdev: u64,
+    /// ki_oncpu, ki_lastcpu, ki_tracer, ki_flag2, ki_fibnum, ki_cr_flags, ki_jid
+    ki_ints: [c_int; 7],
+    ki_numthreads: c_int,
+}
+
+// The offsets in <sys/user.h> on amd64, against which the layout above is checked
+const _: () = assert!(std::mem::offset_of!(KinfoProc, ki_start) == 336);
+const _: () = assert!(std::mem::offset_of!(KinfoProc, ki_comm) == 447);
+const _: () = assert!(std::mem::offset_of!(KinfoProc, ki_numthreads) == 596);
+
+unsafe extern "C" {
+    fn sysctl(name: *const c_int, namelen: c_uint, oldp: *mut c_void, oldlenp: *mut usize, newp: *const c_void, newlen: usize) -> c_int;
+    fn sysctlbyname(name: *const c_char, oldp: *mut c_void, oldlenp: *mut usize, newp: *const c_void, newlen: usize) -> c_int;
+}
+
+/// Reads a variable-sized sysctl value
+fn sysctl_bytes(mib: &[c_int]) -> Option<Vec<u8>> {
+    // The size can grow between the two calls, e.g. when processes are started
+    for _ in 0..3 {
+        let mut len = 0;
+        // SAFETY: a null oldp only queries the size, which is written to len
+        if unsafe { sysctl(mib.as_ptr(), mib.len() as c_uint, std::ptr::null_mut(), &mut len, std::ptr::null(), 0) } != 0 {
+            return None;
+        }
+        len += len / 8;
+        let mut buf = vec![0u8; len];
+        // SAFETY: buf is writable for len bytes, and sysctl updates len to the bytes written
+        if unsafe { sysctl(mib.as_ptr(), mib.len() as c_uint, buf.as_mut_ptr().cast(), &mut len, std::ptr::null(), 0) } == 0 {
+            buf.truncate(len);
+            return Some(buf);
+        }
+    }
+    None
+}
+
+/// Reads a fixed-size numeric sysctl value by name
+fn sysctl_u64(name: &str) -> Option<u64> {
+    let name = CString::new(name).ok()?;
+    let mut value = 0u64;
+    let mut len = size_of::<u64>();
+    // SAFETY: value is writable for len bytes; smaller values fill its first bytes
+    if unsafe { sysctlbyname(name.as_ptr(), (&raw mut value).cast(), &mut len, std::ptr::null(), 0) } != 0 {
+        return None;
+    }
+    // Little endian: a 4-byte int only fills the lower half
+    Some(if len == 4 { value & 0xffff_ffff } else { value })
+}
+
+fn sysctl_string(name: &str) -> Option<String> {
+    let name = CString::new(name).ok()?;
+    let mut buf = [0u8; 256];
+    let mut len = buf.len();
+    // SAFETY: buf is writable for len bytes
+    if unsafe { sysctlbyname(name.as_ptr(), buf.as_mut_ptr().cast(), &mut len, std::ptr::null(), 0) } != 0 {
+        return None;
+    }
+    let s = &buf[..len];
+    Some(String::from_utf8_lossy(s.split(|b| *b == 0).next().unwrap_or(s)).into_owned())
+}
+
+/// Mirrors the fields of /proc/meminfo that reports need
+pub fn read_meminfo() -> HashMap<String, u64> {
+    let mut info = HashMap::new();
+    if let Some(bytes) = sysctl_u64("hw.physmem") {
+        info.insert("MemTotal".to_string(), bytes / 1024);
+    }
+    info
+}
+
+pub fn read_hostname() -> String {
+    sysctl_string("kern.hostname").unwrap_or_default()
+}
+
+pub fn read_cmdline(pid: &str) -> Option<Vec<String>> {
+    let data = sysctl_bytes(&[CTL_KERN, KERN_PROC, KERN_PROC_ARGS, pid.parse().ok()?])?;
+    let parts = data
+        .split(|b| *b == 0u8)
+        .filter(|s| !s.is_empty())
+        .map(|s| String::from_utf8_lossy(s).to_string())
+        .collect();
+    Some(parts)
+}
+
+/// The command name (argv[0] basename)
+pub fn read_cmdname(pid: &str) -> Option<String> {
+    let cmdline = read_cmdline(pid)?;
+    let argv0 = cmdline.first()?.split(' ').next()?;
+    std::path::Path::new(argv0).file_name().map(|s| s.to_string_lossy().to_string())
+}
+
+/// The name the kernel keeps for the process, ki_comm
+pub fn read_comm(pid: &str) -> Option<String> {
+    let data = sysctl_bytes(&[CTL_KERN, KERN_PROC, KERN_PROC_PID, pid.parse().ok()?])?;
+    if data.len() < size_of::<KinfoProc>() {
+        return None;
+    }
+    // SAFETY: the buffer holds a kinfo_proc, of which KinfoProc is a prefix
+    let kp: KinfoProc = unsafe { std::ptr::read_unaligned(data.as_ptr().cast()) };
+    Some(String::from_utf8_lossy(kp.ki_comm.split(|b| *b == 0).next()?).into_owned())
+}
+
+pub fn exe_path(pid: &str) -> Option<String> {
+    let data = sysctl_bytes(&[CTL_KERN, KERN_PROC, KERN_PROC_PATHNAME, pid.parse().ok()?])?;
+    Some(String::from_utf8_lossy(data.split(|b| *b == 0).next()?).into_owned())
+}
+
+pub fn exe_basename(pid: &str) -> Option<String> {
+    let path = exe_path(pid)?;
+    std::path::Path::new(&path).file_name().map(|s| s.to_string_lossy().to_string())
+}
+
+/// Processes are named anew on every scan; the cache is not used
+pub fn collect_processes(config: &Config, _cache: &mut Cache) -> Vec<ProcessSample> {
+    let Some(data) = sysctl_bytes(&[CTL_KERN, KERN_PROC, KERN_PROC_PROC, 0]) else {
+        eprintln!("Failed to read the process list: {}", std::io::Error::last_os_error());
+        std::process::exit(1);
+    };
+    let page_kb = sysctl_u64("hw.pagesize").unwrap_or(4096) / 1024;
+    let users = read_user_names();
+    let mut processes = Vec::new();
+    let mut offset = 0;
+    while offset + size_of::<KinfoProc>() <= data.len() {
+        // SAFETY: the buffer holds a kinfo_proc at offset, of which KinfoProc is a prefix
+        let kp: KinfoProc = unsafe { std::ptr::read_unaligned(data[offset..].as_ptr().cast()) };
+        if kp.ki_structsize <= 0 {
+            break;
+        }
+        offset += kp.ki_structsize as usize;
+
+        let rss_kb = kp.ki_rssize.max(0) as u64 * page_kb;
+        if rss_kb == 0 {
+            continue;
+        }
+        let pid = kp.ki_pid.to_string();
+        let Some(name) = process_key(&ProcessFiles::new(&pid), config.name_from, config.java) else {
+            continue;
+        };
+        processes.push(ProcessSample {
+            pid: kp.ki_pid as u32,
+            ppid: kp.ki_ppid as u32,
+            sid: kp.ki_sid as u32,
+            uid: kp.ki_ruid,
+            user: users.get(&kp.ki_ruid).cloned().unwrap_or_else(|| kp.ki_ruid.to_string()),
+            name,
+            rss_kb,
+            hugetlb_kb: 0,
+            locked_kb: 0,
+            hwm_kb: 0,
+            // ki_size is the current virtual size; FreeBSD keeps no peak
+            peak_kb: 0,
+            swap_kb: 0,
+            threads: kp.ki_numthreads.max(0) as u64,
+            gpu_kb: None,
+            pss_kb: None,
+            uss_kb: None,
+            start_time: config.age.then_some(kp.ki_start[0].max(0) as u64),
+            breakdown: None,
+            dirty: None,
+            numa: None,
+            cgroup: None,
+            cgroup_memory: None,
+            container: None,
+        });
+    }
+    processes
+}
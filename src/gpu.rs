@@ -0,0 +1,82 @@
+//! GPU memory of processes, from the DRM fdinfo interface (Linux 5.19+) and,
+//! for NVIDIA's proprietary driver, from nvidia-smi.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::process::Command;
+
+use crate::procfs;
+
+/// Regions that live in system RAM and are therefore already part of a
+/// process's resident memory; everything else is memory on the device.
+fn is_system_region(region: &str) -> bool {
+    region.starts_with("system") || region.starts_with("stolen") || region == "cpu" || region == "gtt"
+}
+
+/// Parses "<n> KiB" (or B/MiB) into kB
+fn parse_size_kb(value: &str) -> Option<u64> {
+    let mut parts = value.split_whitespace();
+    let n: u64 = parts.next()?.parse().ok()?;
+    Some(match parts.next() {
+        Some("MiB") => n * 1024,
+        Some("GiB") => n * 1024 * 1024,
+        Some("KiB") => n,
+        _ => n / 1024,
+    })
+}
+
+/// Sums the device memory of all DRM clients a process holds open, in kB.
+/// Returns None if the process doesn't use a DRM device.
+pub fn read_drm_memory(pid: &str) -> Option<u64> {
+    let mut seen_clients = HashSet::new();
+    let mut total_kb = None;
+    for entry in fs::read_dir(procfs::path(&format!("/proc/{pid}/fd"))).ok()?.flatten() {
+        let is_drm = fs::read_link(entry.path()).is_ok_and(|target| target.starts_with("/dev/dri/"));
+        if !is_drm {
+            continue;
+        }
+        let Ok(info) = fs::read_to_string(procfs::path(&format!("/proc/{pid}/fdinfo/{}", entry.file_name().to_string_lossy()))) else {
+            continue;
+        };
+        let fields: HashMap<&str, &str> = info.lines().filter_map(|l| l.split_once(':')).map(|(k, v)| (k, v.trim())).collect();
+        // Several file descriptors can refer to the same client
+        let Some(client_id) = fields.get("drm-client-id") else {
+            continue;
+        };
+        let pdev = fields.get("drm-pdev").copied().unwrap_or_default();
+        if !seen_clients.insert(format!("{pdev}/{client_id}")) {
+            continue;
+        }
+        // drm-resident-<region> supersedes the older drm-memory-<region>
+        let prefix = if fields.keys().any(|k| k.starts_with("drm-resident-")) { "drm-resident-" } else { "drm-memory-" };
+        let kb: u64 = fields
+            .iter()
+            .filter_map(|(k, v)| Some((k.strip_prefix(prefix)?, v)))
+            .filter(|(region, _)| !is_system_region(region))
+            .filter_map(|(_, v)| parse_size_kb(v))
+            .sum();
+        *total_kb.get_or_insert(0) += kb;
+    }
+    total_kb
+}
+
+/// GPU memory per pid in kB as reported by nvidia-smi, which only lists compute
+/// applications. Empty if nvidia-smi isn't installed.
+pub fn nvidia_memory() -> HashMap<u32, u64> {
+    let output = Command::new("nvidia-smi")
+        .args(["--query-compute-apps=pid,used_memory", "--format=csv,noheader,nounits"])
+        .output();
+    let Some(output) = output.ok().filter(|o| o.status.success()) else {
+        return HashMap::new();
+    };
+    let mut usage = HashMap::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let Some((pid, mib)) = line.split_once(',') else {
+            continue;
+        };
+        if let (Ok(pid), Ok(mib)) = (pid.trim().parse::<u32>(), mib.trim().parse::<u64>()) {
+            *usage.entry(pid).or_default() += mib * 1024;
+        }
+    }
+    usage
+}
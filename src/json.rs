@@ -0,0 +1,418 @@
+//! A minimal JSON value type with a serializer for the machine-readable output
+//! formats and a parser for reading them back.
+
+use std::fmt::Write;
+
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    /// Fields are kept in insertion order so output is stable and readable
+    Object(Vec<(String, Value)>),
+}
+
+impl From<u64> for Value {
+    fn from(v: u64) -> Value {
+        Value::Number(v as f64)
+    }
+}
+
+impl From<u32> for Value {
+    fn from(v: u32) -> Value {
+        Value::Number(v as f64)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(v: f64) -> Value {
+        Value::Number(v)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Value {
+        Value::String(v.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Value {
+        Value::String(v)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(v: Option<T>) -> Value {
+        v.map_or(Value::Null, Into::into)
+    }
+}
+
+/// Builds an object from (name, value) pairs
+pub fn object<const N: usize>(fields: [(&str, Value); N]) -> Value {
+    Value::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+}
+
+impl Value {
+    /// Looks up a field of an object
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// Serializes the value with two-space indentation
+    pub fn to_pretty_string(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out, 0);
+        out.push('\n');
+        out
+    }
+
+    /// Serializes the value on a single line, e.g. for JSON Lines
+    pub fn to_compact_string(&self) -> String {
+        let mut out = String::new();
+        self.write_compact(&mut out);
+        out
+    }
+
+    fn write_compact(&self, out: &mut String) {
+        match self {
+            Value::Array(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    item.write_compact(out);
+                }
+                out.push(']');
+            }
+            Value::Object(fields) => {
+                out.push('{');
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    write_string(out, key);
+                    out.push(':');
+                    value.write_compact(out);
+                }
+                out.push('}');
+            }
+            scalar => scalar.write(out, 0),
+        }
+    }
+
+    fn write(&self, out: &mut String, indent: usize) {
+        match self {
+            Value::Null => out.push_str("null"),
+            Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            Value::Number(n) if !n.is_finite() => out.push_str("null"),
+            Value::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => {
+                let _ = write!(out, "{}", *n as i64);
+            }
+            Value::Number(n) => {
+                let _ = write!(out, "{n}");
+            }
+            Value::String(s) => write_string(out, s),
+            Value::Array(items) if items.is_empty() => out.push_str("[]"),
+            Value::Array(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    out.push_str(if i == 0 { "\n" } else { ",\n" });
+                    push_indent(out, indent + 1);
+                    item.write(out, indent + 1);
+                }
+                out.push('\n');
+                push_indent(out, indent);
+                out.push(']');
+            }
+            Value::Object(fields) if fields.is_empty() => out.push_str("{}"),
+            Value::Object(fields) => {
+                out.push('{');
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    out.push_str(if i == 0 { "\n" } else { ",\n" });
+                    push_indent(out, indent + 1);
+                    write_string(out, key);
+                    out.push_str(": ");
+                    value.write(out, indent + 1);
+                }
+                out.push('\n');
+                push_indent(out, indent);
+                out.push('}');
+            }
+        }
+    }
+}
+
+fn push_indent(out: &mut String, indent: usize) {
+    for _ in 0..indent {
+        out.push_str("  ");
+    }
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Parses a JSON document; the error describes what was wrong and where
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = Parser { bytes: text.as_bytes(), pos: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos < parser.bytes.len() {
+        return Err(parser.error("trailing characters"));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, msg: &str) -> String {
+        format!("{msg} at byte {}", self.pos)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.bytes.get(self.pos).is_some_and(u8::is_ascii_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &str) -> Result<(), String> {
+        if self.bytes[self.pos..].starts_with(literal.as_bytes()) {
+            self.pos += literal.len();
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected {literal}")))
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.bytes.get(self.pos) {
+            Some(b'n') => self.expect("null").map(|_| Value::Null),
+            Some(b't') => self.expect("true").map(|_| Value::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| Value::Bool(false)),
+            Some(b'"') => self.string().map(Value::String),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = vec![];
+                self.skip_whitespace();
+                if self.bytes.get(self.pos) == Some(&b']') {
+                    self.pos += 1;
+                    return Ok(Value::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.bytes.get(self.pos) {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => break,
+                        _ => return Err(self.error("expected , or ]")),
+                    }
+                }
+                self.pos += 1;
+                Ok(Value::Array(items))
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut fields = vec![];
+                self.skip_whitespace();
+                if self.bytes.get(self.pos) == Some(&b'}') {
+                    self.pos += 1;
+                    return Ok(Value::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.skip_whitespace();
+                    self.expect(":")?;
+                    fields.push((key, self.value()?));
+                    self.skip_whitespace();
+                    match self.bytes.get(self.pos) {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => break,
+                        _ => return Err(self.error("expected , or }")),
+                    }
+                }
+                self.pos += 1;
+                Ok(Value::Object(fields))
+            }
+            Some(b'-' | b'0'..=b'9') => {
+                let start = self.pos;
+                while self.bytes.get(self.pos).is_some_and(|b| b"+-.eE0123456789".contains(b)) {
+                    self.pos += 1;
+                }
+                let number = std::str::from_utf8(&self.bytes[start..self.pos]).unwrap_or_default();
+                number.parse().map(Value::Number).map_err(|_| self.error("invalid number"))
+            }
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if self.bytes.get(self.pos) != Some(&b'"') {
+            return Err(self.error("expected a string"));
+        }
+        self.pos += 1;
+        let mut s = Vec::new();
+        loop {
+            match self.bytes.get(self.pos) {
+                None => return Err(self.error("unterminated string")),
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    let escaped = match self.bytes.get(self.pos + 1) {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            let hex = self.bytes.get(self.pos + 2..self.pos + 6).and_then(|h| std::str::from_utf8(h).ok());
+                            let code = hex.and_then(|h| u32::from_str_radix(h, 16).ok());
+                            self.pos += 4;
+                            // Surrogate pairs are not combined; they never occur in our own output
+                            code.and_then(char::from_u32).unwrap_or(char::REPLACEMENT_CHARACTER)
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    s.extend_from_slice(escaped.encode_utf8(&mut [0; 4]).as_bytes());
+                    self.pos += 2;
+                }
+                Some(b) => {
+                    s.push(*b);
+                    self.pos += 1;
+                }
+            }
+        }
+        self.pos += 1;
+        String::from_utf8(s).map_err(|_| self.error("invalid UTF-8"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses text and serializes it again on one line
+    fn reparse(text: &str) -> String {
+        parse(text).unwrap().to_compact_string()
+    }
+
+    #[test]
+    fn parses_values() {
+        let value = parse(r#" { "a" : [1, -2.5, 1e3, true, false, null], "b": {}, "c": [] } "#).unwrap();
+        let items = value.get("a").and_then(Value::as_array).unwrap();
+        assert_eq!(items.len(), 6);
+        assert_eq!(items[0].as_f64(), Some(1.0));
+        assert_eq!(items[1].as_f64(), Some(-2.5));
+        assert_eq!(items[2].as_f64(), Some(1000.0));
+        assert!(matches!(items[3], Value::Bool(true)));
+        assert!(matches!(items[4], Value::Bool(false)));
+        assert!(matches!(items[5], Value::Null));
+        assert!(value.get("missing").is_none());
+        assert_eq!(value.to_compact_string(), r#"{"a":[1,-2.5,1000,true,false,null],"b":{},"c":[]}"#);
+    }
+
+    #[test]
+    fn keeps_field_order() {
+        assert_eq!(reparse(r#"{"z": 1, "a": 2, "m": 3}"#), r#"{"z":1,"a":2,"m":3}"#);
+    }
+
+    #[test]
+    fn writes_integers_without_fraction() {
+        assert_eq!(Value::from(u64::from(u32::MAX)).to_compact_string(), "4294967295");
+        assert_eq!(Value::from(0.25).to_compact_string(), "0.25");
+        assert_eq!(Value::from(f64::NAN).to_compact_string(), "null");
+        assert_eq!(Value::from(None::<u64>).to_compact_string(), "null");
+        assert_eq!(Value::from(Some(0u64)).to_compact_string(), "0");
+    }
+
+    #[test]
+    fn round_trips_escapes() {
+        let text = "quote \" backslash \\ slash / newline \n return \r tab \t bell \u{7} nul \u{0}";
+        let written = Value::from(text).to_compact_string();
+        assert_eq!(written, r#""quote \" backslash \\ slash / newline \n return \r tab \t bell \u0007 nul \u0000""#);
+        assert_eq!(parse(&written).unwrap().as_str(), Some(text));
+    }
+
+    #[test]
+    fn parses_escapes() {
+        let value = parse(r#""\/\b\f\u00fc\u20AC""#).unwrap();
+        assert_eq!(value.as_str(), Some("/\u{8}\u{c}ü€"));
+    }
+
+    #[test]
+    fn round_trips_non_ascii() {
+        let text = "Grüße, 日本語, 🦀";
+        let written = Value::from(text).to_compact_string();
+        // Written as UTF-8, not as escapes
+        assert_eq!(written, format!("\"{text}\""));
+        assert_eq!(parse(&written).unwrap().as_str(), Some(text));
+    }
+
+    #[test]
+    fn pretty_and_compact_agree() {
+        let value = object([
+            ("name", "a".into()),
+            ("list", Value::Array(vec![1u64.into(), Value::Array(vec![]), object([("x", Value::Null)])])),
+            ("empty", Value::Object(vec![])),
+        ]);
+        let pretty = value.to_pretty_string();
+        assert_eq!(pretty, "{\n  \"name\": \"a\",\n  \"list\": [\n    1,\n    [],\n    {\n      \"x\": null\n    }\n  ],\n  \"empty\": {}\n}\n");
+        assert_eq!(reparse(&pretty), value.to_compact_string());
+    }
+
+    #[test]
+    fn rejects_malformed_documents() {
+        for text in ["", "[1,]", "[1 2]", "{\"a\" 1}", "{a: 1}", "\"open", "\"\\x\"", "1 2", "tru", "-", "{\"a\": 1,}"] {
+            assert!(parse(text).is_err(), "{text:?} should not parse");
+        }
+        assert_eq!(parse("[1] x").err().unwrap(), "trailing characters at byte 4");
+    }
+}
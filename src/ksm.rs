@@ -0,0 +1,87 @@
+//! The `ksm` subcommand: how much memory kernel samepage merging saves.
+//!
+//! System-wide counters come from /sys/kernel/mm/ksm, per-process ones from
+//! /proc/[pid]/ksm_stat (Linux 6.1+) or /proc/[pid]/ksm_merging_pages.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+
+use crate::model::Report;
+use crate::procfs::{self, page_size_kb};
+
+const KSM_DIR: &str = "/sys/kernel/mm/ksm";
+
+fn read_counter(name: &str) -> Option<i64> {
+    fs::read_to_string(procfs::path(&format!("{KSM_DIR}/{name}"))).ok()?.trim().parse().ok()
+}
+
+/// Merged pages of a process and, on newer kernels, the bytes KSM saves for it
+fn read_process_ksm(pid: u32) -> (u64, Option<i64>) {
+    if let Ok(stat) = fs::read_to_string(procfs::path(&format!("/proc/{pid}/ksm_stat"))) {
+        let fields: HashMap<&str, &str> = stat.lines().filter_map(|l| l.split_once(' ')).collect();
+        let merging = fields.get("ksm_merging_pages").and_then(|v| v.trim().parse().ok()).unwrap_or(0);
+        let profit = fields.get("ksm_process_profit").and_then(|v| v.trim().parse().ok());
+        return (merging, profit);
+    }
+    let merging = fs::read_to_string(procfs::path(&format!("/proc/{pid}/ksm_merging_pages")))
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or(0);
+    (merging, None)
+}
+
+pub fn print_ksm(out: &mut dyn Write, report: &Report, limit: usize) -> io::Result<()> {
+    let (Some(run), Some(shared), Some(sharing)) =
+        (read_counter("run"), read_counter("pages_shared"), read_counter("pages_sharing"))
+    else {
+        writeln!(out, "KSM is not available on this system")?;
+        return Ok(());
+    };
+    let page_kb = page_size_kb();
+    let mb = |pages: i64| (pages * page_kb as i64) as f64 / 1024.0;
+
+    let state = match run {
+        0 => "stopped",
+        1 => "running",
+        2 => "unmerging",
+        _ => "unknown",
+    };
+    writeln!(out, "KSM is {state}, {} full scans completed", read_counter("full_scans").unwrap_or(0))?;
+    writeln!(out, "  Shared pages:   {:>10.2} MB", mb(shared))?;
+    writeln!(out, "  Sharing pages:  {:>10.2} MB saved", mb(sharing))?;
+    writeln!(out, "  Unshared pages: {:>10.2} MB scanned without a match", mb(read_counter("pages_unshared").unwrap_or(0)))?;
+    writeln!(out, "  Volatile pages: {:>10.2} MB changing too fast to merge", mb(read_counter("pages_volatile").unwrap_or(0)))?;
+    if let Some(profit) = read_counter("general_profit") {
+        writeln!(out, "  Net profit:     {:>10.2} MB after metadata overhead", profit as f64 / 1024.0 / 1024.0)?;
+    }
+    writeln!(out)?;
+
+    // Without a per-process profit, estimate the saving from the system-wide
+    // ratio: of all merged mappings, only one per shared page is not saved.
+    let saved_ratio = if shared + sharing > 0 { sharing as f64 / (shared + sharing) as f64 } else { 0.0 };
+    let mut rows: Vec<(&str, u64, f64)> = report
+        .groups
+        .iter()
+        .map(|g| {
+            let (mut merged, mut saved_kb) = (0, 0.0);
+            for p in &g.processes {
+                let (pages, profit) = read_process_ksm(p.pid);
+                merged += pages;
+                saved_kb += match profit {
+                    Some(bytes) => bytes as f64 / 1024.0,
+                    None => pages as f64 * page_kb as f64 * saved_ratio,
+                };
+            }
+            (g.name.as_str(), merged, saved_kb)
+        })
+        .filter(|r| r.1 > 0)
+        .collect();
+    rows.sort_by_key(|r| std::cmp::Reverse(r.1));
+
+    writeln!(out, "{:<35} {:>12} {:>12}", "Application", "Merged(MB)", "Saved(MB)")?;
+    for (name, merged, saved_kb) in rows.into_iter().take(limit) {
+        writeln!(out, "{:<35} {:>12.2} {:>12.2}", name, mb(merged as i64), saved_kb / 1024.0)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,97 @@
+//! The `libs` and `files` subcommands: memory of mapped files aggregated
+//! across all processes or the members of one group.
+//!
+//! PSS divides each shared page between the processes mapping it, so summing
+//! it per file yields how much memory a library or binary image really costs,
+//! no matter how many processes share it.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+
+use crate::model::Group;
+use crate::procfs::{self, is_numeric_dir};
+use crate::smaps::read_smaps;
+
+#[derive(Default)]
+struct FileUsage {
+    num: u32,
+    pss_kb: u64,
+    rss_kb: u64,
+}
+
+/// Sums up memory per mapped file over the given processes, largest PSS first.
+/// Also returns the anonymous memory of those processes for comparison.
+fn file_usage(pids: impl Iterator<Item = String>) -> (Vec<(String, FileUsage)>, u64) {
+    let mut by_file: HashMap<String, FileUsage> = HashMap::new();
+    let mut anon_kb = 0;
+    for pid in pids {
+        let Some(mappings) = read_smaps(&pid) else {
+            continue;
+        };
+
+        // A file is usually mapped several times (text, data, ...); count the process once
+        let mut per_process: HashMap<&str, (u64, u64)> = HashMap::new();
+        for m in &mappings {
+            anon_kb += m.anonymous_kb;
+            if m.is_file() {
+                let e = per_process.entry(&m.pathname).or_default();
+                e.0 += m.pss_kb;
+                e.1 += m.rss_kb;
+            }
+        }
+        for (path, (pss_kb, rss_kb)) in per_process {
+            if rss_kb == 0 {
+                continue;
+            }
+            let usage = by_file.entry(path.to_string()).or_default();
+            usage.num += 1;
+            usage.pss_kb += pss_kb;
+            usage.rss_kb += rss_kb;
+        }
+    }
+
+    let mut rows: Vec<(String, FileUsage)> = by_file.into_iter().collect();
+    rows.sort_by_key(|r| std::cmp::Reverse(r.1.pss_kb));
+    (rows, anon_kb)
+}
+
+fn print_files(out: &mut dyn Write, rows: Vec<(String, FileUsage)>, limit: usize) -> io::Result<()> {
+    writeln!(out, "{:<60} {:>5} {:>10} {:>10}", "File", "Procs", "PSS(MB)", "RSS(MB)")?;
+    for (path, usage) in rows.into_iter().take(limit) {
+        writeln!(
+            out,
+            "{:<60} {:>5} {:>10.2} {:>10.2}",
+            path,
+            usage.num,
+            usage.pss_kb as f64 / 1024.0,
+            usage.rss_kb as f64 / 1024.0
+        )?;
+    }
+    Ok(())
+}
+
+pub fn print_libs(out: &mut dyn Write, limit: usize) -> io::Result<()> {
+    let pids = fs::read_dir(procfs::path("/proc"))?
+        .flatten()
+        .filter_map(|e| e.file_name().into_string().ok())
+        .filter(|name| is_numeric_dir(name));
+    let (rows, _) = file_usage(pids);
+    print_files(out, rows, limit)
+}
+
+/// Lists the largest files mapped by the members of one group
+pub fn print_group_files(out: &mut dyn Write, group: &Group, limit: usize) -> io::Result<()> {
+    let (rows, anon_kb) = file_usage(group.processes.iter().map(|p| p.pid.to_string()));
+    let file_kb: u64 = rows.iter().map(|r| r.1.pss_kb).sum();
+    print_files(out, rows, limit)?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "{} ({} processes): {:.2} MB mapped files (PSS), {:.2} MB anonymous",
+        group.name,
+        group.num(),
+        file_kb as f64 / 1024.0,
+        anon_kb as f64 / 1024.0
+    )
+}
@@ -1,215 +1,851 @@
-use std::collections::HashMap;
-use std::fs::{self, File, read_link};
-use std::io::{BufRead, BufReader};
+// Most detail collectors read Linux-only /proc files, and the commands built
+// on sys need Linux on x86_64 or aarch64; their helpers go unused elsewhere
+#![cfg_attr(not(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64"))), allow(dead_code))]
 
-fn read_memtotal_kb() -> Option<u64> {
-    let file = File::open("/proc/meminfo").ok()?;
-    for line in BufReader::new(file).lines().flatten() {
-        if let Some(rest) = line.strip_prefix("MemTotal:") {
-            return rest.split_whitespace().next()?.parse::<u64>().ok();
-        }
-    }
-    None
-}
+use std::fs::File;
+use std::io::{self, IsTerminal, Write};
+use std::time::Duration;
 
-/// Extracts the command name (argv[0] basename) from /proc/[pid]/cmdline
-fn read_cmdname(pid: &str) -> Option<String> {
-    let data = fs::read(format!("/proc/{pid}/cmdline")).ok()?;
-    let mut parts = data.split(|b| *b == 0u8);
-    let argv0 = parts.next()?.split(|b| *b == b' ').next()?; // remove trailing args if embedded
+#[cfg(unix)]
+mod adb;
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod blame;
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod cached;
+#[cfg(unix)]
+mod capture;
+mod cgroup;
+mod classify;
+mod collect;
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod follow;
+#[cfg(target_os = "freebsd")]
+mod freebsd;
+mod compare;
+mod completions;
+mod gpu;
+mod json;
+mod ksm;
+mod libs;
+mod model;
+mod naming;
+mod numa;
+mod oom;
+mod output;
+mod pattern;
+mod pid;
+mod procfs;
+mod remote;
+mod sandbox;
+mod session;
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod run;
+#[cfg(unix)]
+mod shm;
+mod smaps;
+mod swap;
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod sys;
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod tmpfs;
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod top;
+mod treemap;
+#[cfg(windows)]
+mod windows;
 
-    if argv0.is_empty() {
-        return None;
-    }
+use model::SortKey;
+use naming::{Aliases, JavaStrategy, NameSource, Rollup, parse_java_strategy, parse_name_source, parse_rollup};
+use output::{BarScale, Columns};
+use pattern::Pattern;
 
-    let cmd = String::from_utf8_lossy(argv0).to_string();
-    let path = std::path::Path::new(&cmd);
-    path.file_name().map(|s| s.to_string_lossy().to_string())
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Table,
+    Markdown,
+    Json,
+    Treemap,
+    Csv,
 }
 
-fn read_status_vmrss_kb(pid: &str) -> Option<u64> {
-    let file = File::open(format!("/proc/{pid}/status")).ok()?;
-    for line in BufReader::new(file).lines().flatten() {
-        if let Some(rest) = line.strip_prefix("VmRSS:") {
-            return rest.split_whitespace().next()?.parse::<u64>().ok();
-        }
-    }
-    Some(0)
+/// What processes are grouped by
+#[derive(Clone, PartialEq)]
+enum GroupBy {
+    /// The application, the default
+    Name,
+    /// The login session or terminal
+    Session,
+    /// The value of an environment variable; processes without it are grouped by name
+    Env(String),
 }
 
-fn read_cmdline(pid: &str) -> Option<Vec<String>> {
-    let data = fs::read(format!("/proc/{pid}/cmdline")).ok()?;
-    if data.is_empty() {
-        return Some(vec![]);
-    }
-    let parts = data
-        .split(|b| *b == 0u8)
-        .filter(|s| !s.is_empty())
-        .map(|s| String::from_utf8_lossy(s).to_string())
-        .collect::<Vec<_>>();
-    Some(parts)
+/// The subcommand to run; the grouped process report if none is given
+#[derive(Clone, Copy, PartialEq)]
+enum Command {
+    Report,
+    Libs,
+    Files,
+    Ksm,
+    Shm,
+    Tmpfs,
+    Swap,
+    Cached,
+    Oom,
+    Pid,
+    Top,
+    Run,
+    Compare,
+    Follow,
+    Capture,
+    Completions,
+    Blame,
 }
 
-fn exe_basename(pid: &str) -> Option<String> {
-    let p = read_link(format!("/proc/{pid}/exe")).ok()?;
-    Some(p.file_name()?.to_string_lossy().to_string())
+struct Options {
+    command: Command,
+    /// The group selected by subcommands that operate on a single group
+    group: Option<String>,
+    /// The shell to print completions for, or "groups" for the current group names
+    shell: Option<String>,
+    /// Files and directories given to the cached subcommand
+    paths: Vec<String>,
+    /// The command line to execute for run
+    argv: Vec<String>,
+    /// Measure run's command through a cgroup instead of sampling
+    cgroup: bool,
+    /// Selects the groups to follow
+    pattern: Option<Pattern>,
+    /// The process to examine with pid
+    pid: Option<u32>,
+    /// A snapshot written by capture to read instead of the live system
+    from: Option<String>,
+    /// Read an Android device over adb instead of the live system
+    adb: bool,
+    /// The device to read with --adb, if several are connected
+    serial: Option<String>,
+    /// Machines to collect from over SSH instead of this one
+    hosts: Vec<String>,
+    /// The memory binary on the remote hosts
+    remote_command: String,
+    /// Combine the reports of all hosts into one
+    merge_hosts: bool,
+    /// The JSON file to compare against
+    baseline: Option<String>,
+    /// Allowed growth over the baseline in percent
+    max_growth: f64,
+    /// How long blame waits between its samples; until Ctrl-C if None
+    duration: Option<Duration>,
+    limit: usize,
+    name_from: NameSource,
+    java: JavaStrategy,
+    by: GroupBy,
+    /// Attribute helper processes to the application that started them
+    rollup: Option<Rollup>,
+    /// A shell command naming the processes no other rule applies to
+    classify_cmd: Option<String>,
+    /// WebAssembly modules that name processes
+    plugins: Vec<String>,
+    /// The WASI runtime the plugins are run with
+    wasm_runtime: String,
+    /// Application names for groups of process names, from the config file and --alias
+    aliases: Aliases,
+    format: Format,
+    output: Option<String>,
+    nest_users: bool,
+    bars: Option<BarScale>,
+    columns: Columns,
+    sort: SortKey,
+    /// Refresh interval when redrawing the report continuously
+    watch: Option<Duration>,
 }
 
-#[derive(Clone, Copy)]
-enum JavaStrategy {
-    Auto,
-    Jar,
-    Main,
+const USAGE: &str = "\
+Usage: memory [limit] [--name-from=auto|cmdline|exe|comm|path] [--java-by=auto|jar|main]
+              [--rollup=session|launcher] [--alias NAME=PROCESS,...]...
+              [--by=name|session] [--by-env VAR] [--classify-cmd PROG]
+              [--plugin FILE.wasm]... [--wasm-runtime=PROG]
+              [--format=table|markdown|json|treemap] [-o FILE]
+              [--nest=user] [--bars[=largest|total]] [--metrics] [--threads]
+              [--breakdown] [--cgroup-memory] [--hugepages] [--locked] [--dirty] [--peak] [--numa]
+              [--gpu] [--age]
+              [--sort=memory|locked|hwm|peak|swap|gpu|pss|uss|threads]
+              [--watch[=SECS]] [--host [USER@]HOST]... [--merge-hosts] [--remote-command=PATH]
+              [--from SNAPSHOT] [--adb[=SERIAL]]
+       memory libs [limit] [-o FILE]
+       memory files <group> [limit] [--java-by=auto|jar|main] [-o FILE]
+       memory ksm [limit] [--java-by=auto|jar|main] [-o FILE]
+       memory shm [limit] [--java-by=auto|jar|main] [-o FILE]
+       memory tmpfs [limit] [-o FILE]
+       memory swap [limit] [--java-by=auto|jar|main] [-o FILE]
+       memory cached <path>... [-o FILE]
+       memory oom [limit] [--java-by=auto|jar|main] [-o FILE]
+       memory pid <pid> [limit] [--name-from=...] [--java-by=auto|jar|main] [-o FILE]
+       memory top [--java-by=auto|jar|main] [--sort=...] [--watch=SECS]
+       memory run [--cgroup] [--watch=SECS] [--format=table|json] [-o FILE] -- <command> [args...]
+       memory follow <pattern> [--watch=SECS] [--format=table|csv] [--java-by=auto|jar|main] [-o FILE]
+       memory compare --baseline FILE [--max-growth PCT] [current.json] [--java-by=auto|jar|main] [-o FILE]
+       memory blame [limit] [--for DURATION] [--java-by=auto|jar|main] [-o FILE]
+       memory capture -o snapshot.tar[.zst|.gz|.xz]
+       memory completions bash|zsh|fish
+Reports other than top, run, follow, blame, tmpfs and cached can read a snapshot with --from,
+or an Android device with --adb.
+Aliases are also read from ~/.config/memory/aliases, one NAME = PROCESS,... per line.";
+
+fn usage_error(msg: &str) -> ! {
+    eprintln!("{msg}");
+    eprintln!("{USAGE}");
+    std::process::exit(2);
 }
 
-fn parse_java_strategy(arg: Option<String>) -> JavaStrategy {
-    match arg.as_deref() {
-        Some("--java-by=jar") => JavaStrategy::Jar,
-        Some("--java-by=main") => JavaStrategy::Main,
-        _ => JavaStrategy::Auto,
+/// Parses a duration like "90", "90s", "5m", "1.5h" or "2d"; seconds without a unit
+fn parse_duration(s: &str) -> Option<Duration> {
+    let (number, unit) = match s.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let factor = match unit {
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        "d" => 86400.0,
+        _ => return None,
+    };
+    let secs = number.parse::<f64>().ok().filter(|n| *n > 0.0)? * factor;
+    Duration::try_from_secs_f64(secs).ok()
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Options {
+    let mut opts = Options {
+        command: Command::Report,
+        group: None,
+        shell: None,
+        paths: vec![],
+        argv: vec![],
+        cgroup: false,
+        pattern: None,
+        pid: None,
+        from: None,
+        adb: false,
+        serial: None,
+        hosts: vec![],
+        remote_command: "memory".to_string(),
+        merge_hosts: false,
+        baseline: None,
+        max_growth: 10.0,
+        duration: None,
+        limit: 20,
+        name_from: NameSource::Auto,
+        java: JavaStrategy::Auto,
+        by: GroupBy::Name,
+        rollup: None,
+        classify_cmd: None,
+        plugins: vec![],
+        wasm_runtime: "wasmtime".to_string(),
+        aliases: Aliases::default(),
+        format: Format::Table,
+        output: None,
+        nest_users: false,
+        bars: None,
+        columns: Columns::default(),
+        sort: SortKey::Memory,
+        watch: None,
+    };
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        if let Some(v) = arg.strip_prefix("--name-from=") {
+            opts.name_from = parse_name_source(v).unwrap_or_else(|| usage_error(&format!("Unknown name source: {v}")));
+        } else if let Some(v) = arg.strip_prefix("--by=") {
+            opts.by = match v {
+                "name" => GroupBy::Name,
+                "session" => GroupBy::Session,
+                _ => usage_error(&format!("Unknown grouping: {v}")),
+            };
+        } else if arg == "--by-env" || arg.starts_with("--by-env=") {
+            let name = match arg.strip_prefix("--by-env=") {
+                Some(v) => v.to_string(),
+                None => args.next().unwrap_or_else(|| usage_error("Missing variable name after --by-env")),
+            };
+            if name.is_empty() || name.contains('=') {
+                usage_error(&format!("Invalid environment variable name: {name}"));
+            }
+            opts.by = GroupBy::Env(name);
+        } else if arg == "--classify-cmd" {
+            let command = args.next().unwrap_or_else(|| usage_error("Missing command after --classify-cmd"));
+            opts.classify_cmd = Some(command);
+        } else if let Some(v) = arg.strip_prefix("--classify-cmd=") {
+            opts.classify_cmd = Some(v.to_string());
+        } else if arg == "--plugin" {
+            let plugin = args.next().unwrap_or_else(|| usage_error("Missing module after --plugin"));
+            opts.plugins.push(plugin);
+        } else if let Some(v) = arg.strip_prefix("--plugin=") {
+            opts.plugins.push(v.to_string());
+        } else if let Some(v) = arg.strip_prefix("--wasm-runtime=") {
+            opts.wasm_runtime = v.to_string();
+        } else if let Some(v) = arg.strip_prefix("--rollup=") {
+            opts.rollup = Some(parse_rollup(v).unwrap_or_else(|| usage_error(&format!("Unknown rollup heuristic: {v}"))));
+        } else if let Some(v) = arg.strip_prefix("--java-by=") {
+            opts.java = parse_java_strategy(v)
+                .unwrap_or_else(|| usage_error(&format!("Unknown Java naming strategy: {v}")));
+        } else if arg == "--alias" || arg.starts_with("--alias=") {
+            let rule = match arg.strip_prefix("--alias=") {
+                Some(v) => v.to_string(),
+                None => args.next().unwrap_or_else(|| usage_error("Missing NAME=PROCESS,... after --alias")),
+            };
+            opts.aliases.add(&rule).unwrap_or_else(|e| usage_error(&format!("Invalid alias {rule}: {e}")));
+        } else if let Some(v) = arg.strip_prefix("--format=") {
+            opts.format = match v {
+                "table" => Format::Table,
+                "markdown" => Format::Markdown,
+                "json" => Format::Json,
+                "treemap" => Format::Treemap,
+                "csv" => Format::Csv,
+                _ => usage_error(&format!("Unknown output format: {v}")),
+            };
+        } else if arg == "-o" || arg == "--output" {
+            let path = args.next().unwrap_or_else(|| usage_error("Missing file name after -o"));
+            opts.output = Some(path);
+        } else if let Some(v) = arg.strip_prefix("--output=") {
+            opts.output = Some(v.to_string());
+        } else if arg == "--nest=user" {
+            opts.nest_users = true;
+        } else if arg == "--bars" || arg == "--bars=largest" {
+            opts.bars = Some(BarScale::Largest);
+        } else if arg == "--bars=total" {
+            opts.bars = Some(BarScale::Total);
+        } else if arg == "--metrics" {
+            opts.columns.metrics = true;
+        } else if arg == "--threads" {
+            opts.columns.threads = true;
+        } else if arg == "--breakdown" {
+            opts.columns.breakdown = true;
+        } else if arg == "--hugepages" {
+            opts.columns.hugepages = true;
+        } else if arg == "--locked" {
+            opts.columns.locked = true;
+        } else if arg == "--dirty" {
+            opts.columns.dirty = true;
+        } else if arg == "--peak" {
+            opts.columns.peak = true;
+        } else if arg == "--numa" {
+            opts.columns.numa = true;
+        } else if arg == "--gpu" {
+            opts.columns.gpu = true;
+        } else if arg == "--age" {
+            opts.columns.age = true;
+        } else if arg == "--cgroup-memory" {
+            opts.columns.cgroup_memory = true;
+        } else if arg == "--watch" {
+            opts.watch = Some(Duration::from_secs(2));
+        } else if let Some(v) = arg.strip_prefix("--watch=") {
+            let interval = v.parse::<f64>().ok().filter(|s| *s > 0.0).and_then(|s| Duration::try_from_secs_f64(s).ok());
+            opts.watch = Some(interval.unwrap_or_else(|| usage_error(&format!("Invalid watch interval: {v}"))));
+        } else if let Some(v) = arg.strip_prefix("--sort=") {
+            opts.sort = SortKey::parse(v).unwrap_or_else(|| usage_error(&format!("Unknown sort key: {v}")));
+        } else if arg == "libs" && opts.command == Command::Report {
+            opts.command = Command::Libs;
+        } else if arg == "ksm" && opts.command == Command::Report {
+            opts.command = Command::Ksm;
+        } else if arg == "shm" && opts.command == Command::Report {
+            opts.command = Command::Shm;
+        } else if arg == "tmpfs" && opts.command == Command::Report {
+            opts.command = Command::Tmpfs;
+        } else if arg == "swap" && opts.command == Command::Report {
+            opts.command = Command::Swap;
+            opts.sort = SortKey::Swap;
+        } else if arg == "oom" && opts.command == Command::Report {
+            opts.command = Command::Oom;
+        } else if arg == "pid" && opts.command == Command::Report {
+            opts.command = Command::Pid;
+            let pid = args.next().unwrap_or_else(|| usage_error("Missing process id after pid"));
+            opts.pid = Some(pid.parse().unwrap_or_else(|_| usage_error(&format!("Invalid process id: {pid}"))));
+        } else if arg == "top" && opts.command == Command::Report {
+            opts.command = Command::Top;
+        } else if arg == "run" && opts.command == Command::Report {
+            opts.command = Command::Run;
+        } else if arg == "--cgroup" && opts.command == Command::Run {
+            opts.cgroup = true;
+        } else if arg == "--" && opts.command == Command::Run {
+            opts.argv = args.by_ref().collect();
+        } else if arg == "compare" && opts.command == Command::Report {
+            opts.command = Command::Compare;
+        } else if arg == "blame" && opts.command == Command::Report {
+            opts.command = Command::Blame;
+        } else if (arg == "--for" || arg.starts_with("--for=")) && opts.command == Command::Blame {
+            let v = match arg.strip_prefix("--for=") {
+                Some(v) => v.to_string(),
+                None => args.next().unwrap_or_else(|| usage_error("Missing duration after --for")),
+            };
+            opts.duration = Some(parse_duration(&v).unwrap_or_else(|| usage_error(&format!("Invalid duration: {v}"))));
+        } else if arg == "capture" && opts.command == Command::Report {
+            opts.command = Command::Capture;
+        } else if arg == "--from" {
+            let path = args.next().unwrap_or_else(|| usage_error("Missing snapshot after --from"));
+            opts.from = Some(path);
+        } else if let Some(v) = arg.strip_prefix("--from=") {
+            opts.from = Some(v.to_string());
+        } else if arg == "--adb" {
+            opts.adb = true;
+        } else if let Some(v) = arg.strip_prefix("--adb=") {
+            opts.adb = true;
+            opts.serial = Some(v.to_string());
+        } else if arg == "--host" {
+            let host = args.next().unwrap_or_else(|| usage_error("Missing host after --host"));
+            opts.hosts.push(host);
+        } else if let Some(v) = arg.strip_prefix("--host=") {
+            opts.hosts.push(v.to_string());
+        } else if let Some(v) = arg.strip_prefix("--remote-command=") {
+            opts.remote_command = v.to_string();
+        } else if arg == "--merge-hosts" {
+            opts.merge_hosts = true;
+        } else if arg == "--baseline" {
+            let path = args.next().unwrap_or_else(|| usage_error("Missing file name after --baseline"));
+            opts.baseline = Some(path);
+        } else if let Some(v) = arg.strip_prefix("--baseline=") {
+            opts.baseline = Some(v.to_string());
+        } else if arg == "--max-growth" || arg.starts_with("--max-growth=") {
+            let v = match arg.strip_prefix("--max-growth=") {
+                Some(v) => v.to_string(),
+                None => args.next().unwrap_or_else(|| usage_error("Missing percentage after --max-growth")),
+            };
+            let growth = v.trim_end_matches('%').parse::<f64>().ok().filter(|g| *g >= 0.0);
+            opts.max_growth = growth.unwrap_or_else(|| usage_error(&format!("Invalid growth: {v}")));
+        } else if opts.command == Command::Compare && !arg.starts_with('-') && opts.paths.is_empty() {
+            opts.paths.push(arg);
+        } else if arg == "cached" && opts.command == Command::Report {
+            opts.command = Command::Cached;
+        } else if opts.command == Command::Cached && !arg.starts_with('-') {
+            opts.paths.push(arg);
+        } else if arg == "follow" && opts.command == Command::Report {
+            opts.command = Command::Follow;
+            let pattern = args.next().unwrap_or_else(|| usage_error("Missing group pattern after follow"));
+            let pattern = Pattern::parse(&pattern).unwrap_or_else(|e| usage_error(&format!("Invalid pattern {pattern}: {e}")));
+            opts.pattern = Some(pattern);
+        } else if arg == "completions" && opts.command == Command::Report {
+            opts.command = Command::Completions;
+            let shell = args.next().unwrap_or_else(|| usage_error("Missing shell after completions"));
+            if !completions::SHELLS.contains(&shell.as_str()) && shell != "groups" {
+                usage_error(&format!("Unknown shell: {shell}"));
+            }
+            opts.shell = Some(shell);
+        } else if arg == "files" && opts.command == Command::Report {
+            opts.command = Command::Files;
+            let group = args.next().unwrap_or_else(|| usage_error("Missing group name after files"));
+            opts.group = Some(group);
+        } else if let Ok(limit) = arg.parse::<usize>() {
+            opts.limit = limit;
+        } else {
+            usage_error(&format!("Unknown argument: {arg}"));
+        }
+    }
+    if opts.command == Command::Cached && opts.paths.is_empty() {
+        usage_error("Missing path after cached");
+    }
+    let unsupported = !matches!(opts.command, Command::Report | Command::Completions) || opts.watch.is_some() || opts.from.is_some() || opts.adb || opts.by != GroupBy::Name;
+    if cfg!(any(target_os = "freebsd", windows)) && unsupported {
+        usage_error("Only the report is supported on this platform");
+    }
+    // These call into libc with the structures and constants of these targets
+    let libc_commands = [Command::Tmpfs, Command::Cached, Command::Top, Command::Run, Command::Follow, Command::Blame];
+    if !cfg!(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64"))) && libc_commands.contains(&opts.command) {
+        usage_error("This command is only supported on Linux on x86_64 and aarch64");
+    }
+    if opts.command == Command::Capture && opts.output.as_ref().is_none_or(|o| o == "-") {
+        usage_error("capture needs an archive to write to with -o");
+    }
+    let live_only = [Command::Tmpfs, Command::Cached, Command::Shm, Command::Top, Command::Run, Command::Follow, Command::Capture, Command::Blame];
+    if opts.from.is_some() && opts.adb {
+        usage_error("--from and --adb can't be combined");
+    }
+    if (opts.from.is_some() || opts.adb) && (live_only.contains(&opts.command) || opts.watch.is_some() || !opts.hosts.is_empty()) {
+        usage_error("This command only works on the live system, not with --from or --adb");
+    }
+    // Environments often hold secrets and aren't captured; devices are read without cgroups
+    if (opts.from.is_some() || opts.adb) && matches!(opts.by, GroupBy::Env(_)) {
+        usage_error("--by-env only works on the live system, not with --from or --adb");
+    }
+    if opts.adb && opts.columns.cgroup_memory {
+        usage_error("--cgroup-memory can't be combined with --adb");
+    }
+    if !opts.hosts.is_empty() && opts.command != Command::Report {
+        usage_error("--host is only supported by the report");
     }
+    if !opts.hosts.is_empty() && (!opts.plugins.is_empty() || opts.classify_cmd.is_some()) {
+        usage_error("Plugins and classifiers can only name processes of this machine, not with --host");
+    }
+    if opts.hosts.len() > 1 && !opts.merge_hosts && opts.format == Format::Treemap {
+        usage_error("A treemap of several hosts needs --merge-hosts");
+    }
+    if opts.command == Command::Compare && opts.baseline.is_none() {
+        usage_error("compare needs a --baseline");
+    }
+    if opts.command == Command::Run && opts.argv.is_empty() {
+        usage_error("Missing command after run --");
+    }
+    if opts.command == Command::Report && opts.watch.is_some() && (opts.output.is_some() || opts.format != Format::Table) {
+        usage_error("--watch only works with the table format on the terminal");
+    }
+    if opts.command == Command::Top && (opts.output.is_some() || !io::stdin().is_terminal() || !io::stdout().is_terminal()) {
+        usage_error("top needs to run on a terminal");
+    }
+    if opts.command == Command::Run && !matches!(opts.format, Format::Table | Format::Json) {
+        usage_error("run only supports the table and JSON formats");
+    }
+    if opts.command == Command::Follow && !matches!(opts.format, Format::Table | Format::Csv) {
+        usage_error("follow only supports the table and CSV formats");
+    }
+    if opts.command != Command::Follow && opts.format == Format::Csv {
+        usage_error("The CSV format is only supported by follow");
+    }
+    if !matches!(opts.command, Command::Report | Command::Run | Command::Follow) && opts.format != Format::Table {
+        usage_error("Only the table format is supported by subcommands");
+    }
+    // Only commands that build a report name groups, so that a broken config
+    // doesn't get in the way of the others
+    let names_groups = match opts.command {
+        Command::Libs | Command::Tmpfs | Command::Cached | Command::Pid | Command::Run | Command::Capture => false,
+        Command::Compare => opts.paths.is_empty(),
+        Command::Completions => opts.shell.as_deref() == Some("groups"),
+        _ => true,
+    };
+    if names_groups && let Err(e) = opts.aliases.load_config() {
+        eprintln!("{e}");
+        procfs::exit(1);
+    }
+    opts
 }
 
-fn find_jar_name(cmdline: &[String]) -> Option<String> {
-    // Looks for "-jar <file>", returns the JAR's basename
-    let mut i = 1; // skip argv[0] ("java")
-    while i < cmdline.len() {
-        let tok = &cmdline[i];
-        if tok == "-jar" {
-            if i + 1 < cmdline.len() {
-                let jar = std::path::Path::new(&cmdline[i + 1]);
-                return jar.file_name().map(|f| f.to_string_lossy().to_string());
-            } else {
-                return None;
+/// Builds the report of this machine; cache carries state from one scan to
+/// the next in the continuous modes
+fn build_report(opts: &Options, cache: &mut collect::Cache) -> model::Report {
+    let meminfo = procfs::read_meminfo();
+    if meminfo.get("MemTotal").is_none_or(|v| *v == 0) {
+        eprintln!("Could not read MemTotal from /proc/meminfo");
+        procfs::exit(1);
+    }
+
+    let config = collect::Config {
+        name_from: opts.name_from,
+        java: opts.java,
+        breakdown: opts.columns.breakdown,
+        dirty: opts.columns.dirty,
+        numa: opts.columns.numa,
+        gpu: opts.columns.gpu || matches!(opts.sort, SortKey::Gpu),
+        // The PSS and USS to sort by come from smaps_rollup like the other metrics
+        metrics: opts.columns.metrics || matches!(opts.sort, SortKey::Pss | SortKey::Uss),
+        age: opts.columns.age,
+        cgroup_memory: opts.columns.cgroup_memory,
+        swap: opts.columns.metrics || matches!(opts.sort, SortKey::Swap) || opts.command == Command::Swap,
+        hugetlb: opts.columns.hugepages,
+    };
+    let mut samples = collect::collect_processes(&config, cache);
+    if let Some(classify_cmd) = &opts.classify_cmd {
+        let mut unknown: Vec<_> = samples
+            .iter_mut()
+            .filter(|p| opts.aliases.get(&p.name).is_none() && !naming::is_derived_name(&p.name) && p.container.is_none())
+            .collect();
+        let command = if cfg!(windows) {
+            let mut command = std::process::Command::new("cmd");
+            command.arg("/C").arg(classify_cmd);
+            command
+        } else {
+            let mut command = std::process::Command::new("sh");
+            command.arg("-c").arg(classify_cmd);
+            command
+        };
+        let memo = cache.classified.entry(classify_cmd.clone()).or_default();
+        if let Err(e) = classify::classify_memoized(command, &mut unknown, memo) {
+            eprintln!("The classifier command failed: {e}");
+            procfs::exit(1);
+        }
+    }
+    for plugin in &opts.plugins {
+        let mut command = std::process::Command::new(&opts.wasm_runtime);
+        command.arg("run").arg(plugin);
+        let memo = cache.classified.entry(plugin.clone()).or_default();
+        if let Err(e) = classify::classify_memoized(command, &mut samples.iter_mut().collect::<Vec<_>>(), memo) {
+            match e.kind() {
+                io::ErrorKind::NotFound => eprintln!("Failed to run plugin {plugin}: {} not found", opts.wasm_runtime),
+                _ => eprintln!("Plugin {plugin} failed: {e}"),
             }
+            procfs::exit(1);
         }
-        if tok.starts_with('-') {
-            // skip JVM options; handle options with a separate argument
-            if tok == "-cp" || tok == "-classpath" || tok == "--class-path" {
-                i += 2;
-            } else {
-                i += 1;
+    }
+    if let Some(rollup) = opts.rollup {
+        naming::roll_up(&mut samples, rollup);
+    }
+    for p in &mut samples {
+        if let Some(name) = opts.aliases.get(&p.name) {
+            p.name = name.to_string();
+        }
+    }
+    match &opts.by {
+        GroupBy::Name => {
+            for p in &mut samples {
+                if let Some(container) = &p.container {
+                    p.name = naming::container_key(&p.name, container);
+                }
+            }
+        }
+        GroupBy::Session => {
+            for p in &mut samples {
+                p.name = session::session_name(p.pid);
+            }
+        }
+        GroupBy::Env(var) => {
+            for p in &mut samples {
+                if let Some(value) = procfs::read_environ_var(&p.pid.to_string(), var) {
+                    p.name = format!("{var}={value}");
+                }
             }
-            continue;
         }
-        break;
     }
-    None
+    let mut report = model::Report::new(procfs::read_hostname(), &meminfo, samples);
+    report.sort(opts.sort);
+    report
 }
 
-fn find_main_class(cmdline: &[String]) -> Option<String> {
-    // Skips JVM options to the first non-option token (the main class)
-    let mut i = 1; // skip "java"
-    while i < cmdline.len() && cmdline[i].starts_with('-') {
-        if cmdline[i] == "-cp" || cmdline[i] == "-classpath" || cmdline[i] == "--class-path" {
-            i += 2;
-        } else {
-            i += 1;
+/// Looks up the group selected on the command line
+fn find_group<'a>(report: &'a model::Report, name: &str) -> &'a model::Group {
+    report.groups.iter().find(|g| g.name == name).unwrap_or_else(|| {
+        eprintln!("No running processes in group {name}");
+        procfs::exit(1);
+    })
+}
+
+/// Builds the report of each host given with --host, or of this machine
+fn build_reports(opts: &Options, cache: &mut collect::Cache) -> Vec<model::Report> {
+    if opts.hosts.is_empty() {
+        return vec![build_report(opts, cache)];
+    }
+    // All groups, so that they can be merged and sorted here
+    let mut args = vec![
+        "--format=json".to_string(),
+        u32::MAX.to_string(),
+        format!("--name-from={}", opts.name_from.name()),
+        format!("--java-by={}", opts.java.name()),
+        // The remote side collects the columns the key needs and keeps the
+        // processes it would otherwise drop, e.g. entirely swapped out ones
+        format!("--sort={}", opts.sort.name()),
+    ];
+    if let Some(rollup) = opts.rollup {
+        args.push(format!("--rollup={}", rollup.name()));
+    }
+    match &opts.by {
+        GroupBy::Name => {}
+        GroupBy::Session => args.push("--by=session".to_string()),
+        GroupBy::Env(var) => args.push(format!("--by-env={var}")),
+    }
+    for (enabled, flag) in [
+        (opts.columns.breakdown, "--breakdown"),
+        (opts.columns.dirty, "--dirty"),
+        (opts.columns.numa, "--numa"),
+        (opts.columns.gpu, "--gpu"),
+        (opts.columns.metrics, "--metrics"),
+        (opts.columns.age, "--age"),
+        (opts.columns.cgroup_memory, "--cgroup-memory"),
+        (opts.columns.hugepages, "--hugepages"),
+    ] {
+        if enabled {
+            args.push(flag.to_string());
+        }
+    }
+    let mut reports = remote::collect_reports(&opts.hosts, &opts.remote_command, &args);
+    if reports.is_empty() {
+        eprintln!("None of the hosts could be queried");
+        procfs::exit(1);
+    }
+    if !opts.aliases.is_empty() {
+        for report in &mut reports {
+            report.rename(|name| opts.aliases.get(name).map(str::to_string));
         }
     }
-    cmdline.get(i).cloned()
+    if opts.merge_hosts {
+        reports = vec![model::Report::merge(reports)];
+    }
+    for report in &mut reports {
+        report.sort(opts.sort);
+    }
+    reports
 }
 
-/// Try to produce a nicer name for a Java process:
-/// - If "-jar X" is present -> basename(X)
-/// - Else first non-option token after JVM flags -> main class
-fn java_display_name(cmdline: &[String], strat: JavaStrategy) -> Option<String> {
-    match strat {
-        JavaStrategy::Jar => find_jar_name(cmdline),
-        JavaStrategy::Main => find_main_class(cmdline),
-        JavaStrategy::Auto => find_jar_name(cmdline).or_else(|| find_main_class(cmdline)),
+/// Prints the grouped process report, the default command
+fn print_report(out: &mut dyn Write, opts: &Options, cache: &mut collect::Cache) -> io::Result<()> {
+    let reports = build_reports(opts, cache);
+    // Several hosts are listed one after the other with their name as heading
+    let several = reports.len() > 1;
+
+    match opts.format {
+        Format::Table => {
+            let color = opts.output.is_none() && io::stdout().is_terminal();
+            for (i, report) in reports.iter().enumerate() {
+                if several {
+                    let separator = if i > 0 { "\n" } else { "" };
+                    writeln!(out, "{separator}Host: {}\n", report.hostname)?;
+                }
+                output::print_pressure(out, report, color)?;
+                output::print_table(out, report, opts.limit, &opts.columns, opts.bars, opts.nest_users)?;
+            }
+            Ok(())
+        }
+        Format::Markdown => {
+            for (i, report) in reports.iter().enumerate() {
+                if several {
+                    let separator = if i > 0 { "\n" } else { "" };
+                    writeln!(out, "{separator}## {}\n", report.hostname)?;
+                }
+                output::print_markdown(out, report, opts.limit, &opts.columns, opts.nest_users)?;
+            }
+            Ok(())
+        }
+        Format::Json if several => {
+            let reports = json::Value::Array(reports.iter().map(|r| r.to_json(opts.limit)).collect());
+            out.write_all(reports.to_pretty_string().as_bytes())
+        }
+        Format::Json => output::print_json(out, &reports[0], opts.limit),
+        Format::Treemap => output::write_treemap(out, &reports[0], opts.limit, opts.nest_users),
+        Format::Csv => unreachable!("rejected by parse_args"),
     }
 }
 
-fn is_numeric_dir(name: &str) -> bool {
-    !name.is_empty() && name.chars().all(|c| c.is_ascii_digit())
+/// Redraws the report at the given interval until interrupted
+fn watch(out: &mut dyn Write, opts: &Options, interval: Duration) -> io::Result<()> {
+    let mut cache = collect::Cache::default();
+    loop {
+        // Render off-screen first so the terminal doesn't flicker
+        let mut frame = Vec::new();
+        print_report(&mut frame, opts, &mut cache)?;
+        out.write_all(b"\x1b[H\x1b[2J")?;
+        out.write_all(&frame)?;
+        out.flush()?;
+        std::thread::sleep(interval);
+    }
 }
 
-struct MapEntry {
-    num: u32,
-    memory: u64,
+/// Opens the report destination: the file given with -o, or stdout
+fn open_output(path: Option<&str>) -> Box<dyn Write> {
+    match path {
+        None | Some("-") => Box::new(io::stdout()),
+        Some(path) => match File::create(path) {
+            Ok(f) => Box::new(io::BufWriter::new(f)),
+            Err(e) => {
+                eprintln!("Failed to create {path}: {e}");
+                procfs::exit(1);
+            }
+        },
+    }
 }
 
 fn main() {
-    // Args: [limit] [--java-by=auto|jar|main]
-    let mut args = std::env::args().skip(1).collect::<Vec<_>>();
-    let java_arg = args.iter().find(|a| a.starts_with("--java-by=")).cloned();
-    args.retain(|a| !a.starts_with("--java-by="));
-    let limit: usize = args.get(0).and_then(|s| s.parse().ok()).unwrap_or(20);
-    let jstrategy = parse_java_strategy(java_arg);
-
-    let total_kb = match read_memtotal_kb() {
-        Some(v) if v > 0 => v,
-        _ => {
-            eprintln!("Could not read MemTotal from /proc/meminfo");
-            std::process::exit(1);
-        }
+    let opts = parse_args(std::env::args().skip(1));
+    #[cfg(unix)]
+    let snapshot = match &opts.from {
+        Some(path) => Some(capture::Snapshot::open(path)),
+        None if opts.adb => Some(adb::pull(opts.serial.as_deref()).unwrap_or_else(|e| {
+            eprintln!("Failed to read the device: {e}");
+            procfs::exit(1);
+        })),
+        None => None,
     };
+    #[cfg(unix)]
+    if let Some(snapshot) = &snapshot {
+        procfs::set_root(snapshot.root.clone(), snapshot.extracted);
+    }
 
-    let mut by_key: HashMap<String, MapEntry> = HashMap::new();
-
-    let proc = match fs::read_dir("/proc") {
-        Ok(d) => d,
-        Err(e) => {
-            eprintln!("Failed to read /proc: {e}");
-            std::process::exit(1);
-        }
+    let mut out: Box<dyn Write> = match (opts.command, &opts.output) {
+        // Keep the summary apart from the command's own output, like time(1)
+        #[cfg(unix)]
+        (Command::Run, None) => Box::new(io::stderr()),
+        // -o names the archive, which tar writes
+        #[cfg(unix)]
+        (Command::Capture, _) => Box::new(io::stdout()),
+        _ => open_output(opts.output.as_deref()),
     };
-
-    for entry in proc.flatten() {
-        let name = match entry.file_name().into_string() {
-            Ok(s) => s,
-            Err(_) => continue,
-        };
-        if !is_numeric_dir(&name) {
-            continue;
+    let mut exit_code = 0;
+    // Only reused by the commands that scan repeatedly
+    let mut cache = collect::Cache::default();
+    let result = match opts.command {
+        Command::Report => match opts.watch {
+            Some(interval) => watch(&mut out, &opts, interval),
+            None => print_report(&mut out, &opts, &mut cache),
+        },
+        Command::Libs => libs::print_libs(&mut out, opts.limit),
+        Command::Files => {
+            let report = build_report(&opts, &mut cache);
+            let group = find_group(&report, opts.group.as_deref().unwrap_or_default());
+            libs::print_group_files(&mut out, group, opts.limit)
         }
-
-        // Processes vanish; ignore errors quietly.
-        let rss_kb = match read_status_vmrss_kb(&name) {
-            Some(v) => v,
-            None => continue,
-        };
-        if rss_kb == 0 {
-            continue;
+        Command::Ksm => ksm::print_ksm(&mut out, &build_report(&opts, &mut cache), opts.limit),
+        #[cfg(unix)]
+        Command::Shm => shm::print_shm(&mut out, &build_report(&opts, &mut cache), opts.limit),
+        #[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+        Command::Tmpfs => tmpfs::print_tmpfs(&mut out, opts.limit),
+        Command::Swap => swap::print_swap(&mut out, &build_report(&opts, &mut cache), opts.limit),
+        #[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+        Command::Cached => cached::print_cached(&mut out, &opts.paths),
+        Command::Oom => oom::print_oom(&mut out, &build_report(&opts, &mut cache), opts.limit),
+        Command::Pid => pid::print_process(&mut out, opts.pid.expect("set by parse_args"), opts.name_from, opts.java, opts.limit),
+        #[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+        Command::Top => top::run(|| build_report(&opts, &mut cache), opts.watch.unwrap_or(Duration::from_secs(2))),
+        #[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+        Command::Run => {
+            let interval = opts.watch.unwrap_or(Duration::from_millis(100));
+            let json = opts.format == Format::Json;
+            let result = if opts.cgroup {
+                run::run_in_cgroup(&mut out, &opts.argv, interval, json)
+            } else {
+                run::run_command(&mut out, &opts.argv, interval, json)
+            };
+            result.map(|code| exit_code = code)
         }
+        #[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+        Command::Follow => {
+            let pattern = opts.pattern.as_ref().expect("set by parse_args");
+            let interval = opts.watch.unwrap_or(Duration::from_secs(2));
+            follow::follow(&mut out, pattern, || build_report(&opts, &mut cache), interval, opts.format == Format::Csv)
+        }
+        #[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+        Command::Blame => blame::blame(&mut out, || build_report(&opts, &mut cache), opts.duration, opts.limit),
+        #[cfg(unix)]
+        Command::Capture => capture::capture(&mut out, opts.output.as_deref().unwrap_or_default()),
+        Command::Compare => {
+            let baseline = opts.baseline.as_deref().unwrap_or_default();
+            let current = opts.paths.first().map(String::as_str);
+            compare::compare(&mut out, baseline, current, || build_report(&opts, &mut cache), opts.max_growth).map(|code| exit_code = code)
+        }
+        Command::Completions => match opts.shell.as_deref().unwrap_or_default() {
+            "groups" => completions::print_groups(&mut out, &build_report(&opts, &mut cache)),
+            shell => completions::print_completions(&mut out, shell),
+        },
+        #[cfg(not(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64"))))]
+        _ => unreachable!("rejected by parse_args"),
+    };
+    if let Err(e) = result.and_then(|_| out.flush()) {
+        eprintln!("Failed to write report: {e}");
+        procfs::exit(1);
+    }
+    procfs::exit(exit_code);
+}
 
-        let comm = match read_cmdname(&name) {
-            Some(c) if !c.is_empty() => c,
-            _ => continue,
-        };
-
-        let key = if comm == "java" || comm == "javaw" {
-            let cmdline = read_cmdline(&name).unwrap_or_default();
-            if let Some(app) = java_display_name(&cmdline, jstrategy) {
-                let app = app.rsplit('.').next().unwrap_or(&app).to_string();
-                format!("java: {}", app)
-            } else {
-                let exe = exe_basename(&name).unwrap_or_else(|| "java".to_string());
-                format!("java ({exe})")
-            }
-        } else {
-            comm
-        };
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        by_key.entry(key)
-            .and_modify(|e| {
-                e.num += 1;
-                e.memory += rss_kb;
-            })
-            .or_insert(MapEntry {num: 1, memory: rss_kb});
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("90s"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("5m"), Some(Duration::from_secs(300)));
+        assert_eq!(parse_duration("1.5h"), Some(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("2d"), Some(Duration::from_secs(172_800)));
+        assert_eq!(parse_duration("0.25"), Some(Duration::from_millis(250)));
     }
 
-    let mut rows: Vec<(String, MapEntry)> = by_key.into_iter().collect();
-    rows.sort_by(|a, b| b.1.memory.cmp(&a.1.memory));
-
-    println!("{:<35} {:>4} {:>12} {:>8} {:>8}", "Application", "Num", "Memory(MB)", "%", "Cum.%");
-    let mut cum = 0.0_f64;
-    for (key, entry) in rows.into_iter().take(limit) {
-        let mb = (entry.memory as f64) / 1024.0;
-        let pct = (entry.memory as f64) * 100.0 / (total_kb as f64);
-        cum += pct;
-        println!("{:<35} {:>4} {:>12.2} {:>7.2}% {:>7.2}%", key, entry.num, mb, pct, cum);
+    #[test]
+    fn rejects_invalid_durations() {
+        for s in ["", "s", "0", "0s", "-5m", "5w", "5ms", "m5", "1e3", "inf", "NaN", "5 m", "1e400"] {
+            assert_eq!(parse_duration(s), None, "{s:?}");
+        }
+        // Too long to represent
+        assert_eq!(parse_duration(&format!("{}d", u64::MAX)), None);
     }
 }
-
@@ -0,0 +1,712 @@
+//! The data model behind every report.
+//!
+//! All output formats render from a [`Report`]; the machine-readable formats
+//! serialize it as-is, so its shape is part of the tool's interface. Bump
+//! [`SCHEMA_VERSION`] whenever a field is renamed, removed or changes meaning.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::cgroup::MemoryStat;
+use crate::json::{self, Value};
+use crate::naming::container_key;
+use crate::numa::NodeUsage;
+use crate::smaps::{Breakdown, Dirty};
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A single process as sampled from /proc
+#[derive(Clone)]
+pub struct ProcessSample {
+    pub pid: u32,
+    /// The parent process
+    pub ppid: u32,
+    /// The session, identified by the pid of its leader; 0 if unknown
+    pub sid: u32,
+    pub uid: u32,
+    pub user: String,
+    /// The application name the process is grouped under
+    pub name: String,
+    pub rss_kb: u64,
+    /// Memory in hugetlbfs pages, which is not included in rss_kb
+    pub hugetlb_kb: u64,
+    pub locked_kb: u64,
+    /// High-water mark of rss_kb
+    pub hwm_kb: u64,
+    /// Peak virtual memory size
+    pub peak_kb: u64,
+    pub swap_kb: u64,
+    pub threads: u64,
+    /// Memory on a GPU; only sampled when requested, None if no GPU is used
+    pub gpu_kb: Option<u64>,
+    /// Proportional and unique set size; only sampled when requested, None if
+    /// smaps_rollup isn't readable
+    pub pss_kb: Option<u64>,
+    pub uss_kb: Option<u64>,
+    /// Seconds since the Unix epoch at which the process was started; only
+    /// sampled when process ages were requested
+    pub start_time: Option<u64>,
+    /// Only sampled when a breakdown was requested
+    pub breakdown: Option<Breakdown>,
+    /// Only sampled when dirty pages were requested
+    pub dirty: Option<Dirty>,
+    /// Only sampled when a NUMA breakdown was requested
+    pub numa: Option<NodeUsage>,
+    /// The cgroup the process runs in and that cgroup's memory.stat; only
+    /// sampled when requested
+    pub cgroup: Option<String>,
+    pub cgroup_memory: Option<MemoryStat>,
+    /// The container the process runs in, which its name ends with once
+    /// aliases have been applied
+    pub container: Option<String>,
+}
+
+/// Processes aggregated under a common name
+pub struct Group {
+    pub name: String,
+    pub rss_kb: u64,
+    pub hugetlb_kb: u64,
+    pub locked_kb: u64,
+    pub hwm_kb: u64,
+    pub peak_kb: u64,
+    pub swap_kb: u64,
+    pub threads: u64,
+    pub gpu_kb: Option<u64>,
+    pub pss_kb: Option<u64>,
+    pub uss_kb: Option<u64>,
+    /// Start times of the group's longest and most recently started processes
+    pub oldest_start: Option<u64>,
+    pub newest_start: Option<u64>,
+    pub breakdown: Option<Breakdown>,
+    pub dirty: Option<Dirty>,
+    pub numa: Option<NodeUsage>,
+    /// The memory.stat of the cgroup, if all processes run in the same one
+    /// and no other group's do
+    pub cgroup_memory: Option<MemoryStat>,
+    pub processes: Vec<ProcessSample>,
+}
+
+/// Memory pressure stall information from /proc/pressure/memory: the share of
+/// time in percent in which some or all tasks were stalled waiting for memory,
+/// averaged over 10, 60 and 300 seconds
+#[derive(Clone, Copy, Default)]
+pub struct Pressure {
+    pub some: [f64; 3],
+    pub full: [f64; 3],
+}
+
+impl Pressure {
+    fn to_json(self) -> Value {
+        let avgs = |a: [f64; 3]| json::object([("avg10", a[0].into()), ("avg60", a[1].into()), ("avg300", a[2].into())]);
+        json::object([("some", avgs(self.some)), ("full", avgs(self.full))])
+    }
+
+    fn from_json(value: &Value) -> Pressure {
+        let avgs = |kind: &str| {
+            let get = |avg: &str| value.get(kind).and_then(|v| v.get(avg)).and_then(Value::as_f64).unwrap_or(0.0);
+            [get("avg10"), get("avg60"), get("avg300")]
+        };
+        Pressure { some: avgs("some"), full: avgs("full") }
+    }
+}
+
+/// The value groups are ordered by, always largest first
+#[derive(Clone, Copy)]
+pub enum SortKey {
+    Memory,
+    Locked,
+    Hwm,
+    Peak,
+    Swap,
+    Gpu,
+    Pss,
+    Uss,
+    Threads,
+}
+
+impl SortKey {
+    pub fn parse(value: &str) -> Option<SortKey> {
+        match value {
+            "memory" => Some(SortKey::Memory),
+            "locked" => Some(SortKey::Locked),
+            "hwm" => Some(SortKey::Hwm),
+            "peak" => Some(SortKey::Peak),
+            "swap" => Some(SortKey::Swap),
+            "gpu" => Some(SortKey::Gpu),
+            "pss" => Some(SortKey::Pss),
+            "uss" => Some(SortKey::Uss),
+            "threads" => Some(SortKey::Threads),
+            _ => None,
+        }
+    }
+
+    /// The value of --sort selecting this key
+    pub fn name(self) -> &'static str {
+        match self {
+            SortKey::Memory => "memory",
+            SortKey::Locked => "locked",
+            SortKey::Hwm => "hwm",
+            SortKey::Peak => "peak",
+            SortKey::Swap => "swap",
+            SortKey::Gpu => "gpu",
+            SortKey::Pss => "pss",
+            SortKey::Uss => "uss",
+            SortKey::Threads => "threads",
+        }
+    }
+
+    fn value(self, group: &Group) -> u64 {
+        match self {
+            SortKey::Memory => group.rss_kb,
+            SortKey::Locked => group.locked_kb,
+            SortKey::Hwm => group.hwm_kb,
+            SortKey::Peak => group.peak_kb,
+            SortKey::Swap => group.swap_kb,
+            SortKey::Gpu => group.gpu_kb.unwrap_or(0),
+            SortKey::Pss => group.pss_kb.unwrap_or(0),
+            SortKey::Uss => group.uss_kb.unwrap_or(0),
+            SortKey::Threads => group.threads,
+        }
+    }
+}
+
+/// A snapshot of memory usage on one machine
+pub struct Report {
+    pub schema_version: u32,
+    /// Seconds since the Unix epoch at which the report was taken
+    pub timestamp: u64,
+    pub hostname: String,
+    pub mem_total_kb: u64,
+    pub hugepages: HugepagePool,
+    /// None if the kernel doesn't support PSI
+    pub pressure: Option<Pressure>,
+    /// System-wide memory waiting to be written back to disk
+    pub dirty_kb: u64,
+    /// System-wide memory actively being written back to disk
+    pub writeback_kb: u64,
+    /// Groups by application name, largest first
+    pub groups: Vec<Group>,
+}
+
+/// The system's pool of preallocated huge pages (counts in pages)
+#[derive(Clone, Copy, Default)]
+pub struct HugepagePool {
+    pub total: u64,
+    pub free: u64,
+    /// Promised to a mapping but not faulted in yet; part of free
+    pub reserved: u64,
+    pub surplus: u64,
+    pub page_size_kb: u64,
+}
+
+impl HugepagePool {
+    pub fn from_meminfo(meminfo: &HashMap<String, u64>) -> HugepagePool {
+        let get = |key: &str| meminfo.get(key).copied().unwrap_or(0);
+        HugepagePool {
+            total: get("HugePages_Total"),
+            free: get("HugePages_Free"),
+            reserved: get("HugePages_Rsvd"),
+            surplus: get("HugePages_Surp"),
+            page_size_kb: get("Hugepagesize"),
+        }
+    }
+
+    /// Pages that are actually in use by some mapping
+    pub fn used(&self) -> u64 {
+        self.total.saturating_sub(self.free)
+    }
+
+    fn from_json(value: &Value) -> HugepagePool {
+        HugepagePool {
+            total: u64_field(value, "total"),
+            free: u64_field(value, "free"),
+            reserved: u64_field(value, "reserved"),
+            surplus: u64_field(value, "surplus"),
+            page_size_kb: u64_field(value, "page_size_kb"),
+        }
+    }
+
+    fn to_json(self) -> Value {
+        json::object([
+            ("total", self.total.into()),
+            ("free", self.free.into()),
+            ("reserved", self.reserved.into()),
+            ("surplus", self.surplus.into()),
+            ("page_size_kb", self.page_size_kb.into()),
+        ])
+    }
+}
+
+impl Group {
+    pub fn num(&self) -> usize {
+        self.processes.len()
+    }
+
+    fn to_json(&self) -> Value {
+        json::object([
+            ("name", self.name.as_str().into()),
+            ("num", (self.num() as u64).into()),
+            ("rss_kb", self.rss_kb.into()),
+            ("hugetlb_kb", self.hugetlb_kb.into()),
+            ("locked_kb", self.locked_kb.into()),
+            ("hwm_kb", self.hwm_kb.into()),
+            ("peak_kb", self.peak_kb.into()),
+            ("swap_kb", self.swap_kb.into()),
+            ("threads", self.threads.into()),
+            ("gpu_kb", self.gpu_kb.into()),
+            ("pss_kb", self.pss_kb.into()),
+            ("uss_kb", self.uss_kb.into()),
+            ("oldest_start", self.oldest_start.into()),
+            ("newest_start", self.newest_start.into()),
+            ("breakdown", self.breakdown.map(breakdown_json).into()),
+            ("dirty", self.dirty.map(dirty_json).into()),
+            ("numa_kb", self.numa.as_ref().map(numa_json).into()),
+            ("cgroup_memory", self.cgroup_memory.map(memory_stat_json).into()),
+            ("processes", Value::Array(self.processes.iter().map(ProcessSample::to_json).collect())),
+        ])
+    }
+}
+
+impl ProcessSample {
+    fn to_json(&self) -> Value {
+        json::object([
+            ("pid", self.pid.into()),
+            ("ppid", self.ppid.into()),
+            ("sid", self.sid.into()),
+            ("uid", self.uid.into()),
+            ("user", self.user.as_str().into()),
+            ("name", self.name.as_str().into()),
+            ("rss_kb", self.rss_kb.into()),
+            ("hugetlb_kb", self.hugetlb_kb.into()),
+            ("locked_kb", self.locked_kb.into()),
+            ("hwm_kb", self.hwm_kb.into()),
+            ("peak_kb", self.peak_kb.into()),
+            ("swap_kb", self.swap_kb.into()),
+            ("threads", self.threads.into()),
+            ("gpu_kb", self.gpu_kb.into()),
+            ("pss_kb", self.pss_kb.into()),
+            ("uss_kb", self.uss_kb.into()),
+            ("start_time", self.start_time.into()),
+            ("breakdown", self.breakdown.map(breakdown_json).into()),
+            ("dirty", self.dirty.map(dirty_json).into()),
+            ("numa_kb", self.numa.as_ref().map(numa_json).into()),
+            ("cgroup", self.cgroup.as_deref().into()),
+            ("cgroup_memory", self.cgroup_memory.map(memory_stat_json).into()),
+            ("container", self.container.as_deref().into()),
+        ])
+    }
+}
+
+impl ProcessSample {
+    fn from_json(value: &Value) -> ProcessSample {
+        let optional = |key: &str| value.get(key).filter(|v| !matches!(v, Value::Null));
+        ProcessSample {
+            pid: u64_field(value, "pid") as u32,
+            ppid: u64_field(value, "ppid") as u32,
+            sid: u64_field(value, "sid") as u32,
+            uid: u64_field(value, "uid") as u32,
+            user: value.get("user").and_then(Value::as_str).unwrap_or_default().to_string(),
+            name: value.get("name").and_then(Value::as_str).unwrap_or_default().to_string(),
+            rss_kb: u64_field(value, "rss_kb"),
+            hugetlb_kb: u64_field(value, "hugetlb_kb"),
+            locked_kb: u64_field(value, "locked_kb"),
+            hwm_kb: u64_field(value, "hwm_kb"),
+            peak_kb: u64_field(value, "peak_kb"),
+            swap_kb: u64_field(value, "swap_kb"),
+            threads: u64_field(value, "threads"),
+            gpu_kb: optional("gpu_kb").and_then(Value::as_f64).map(|kb| kb as u64),
+            pss_kb: optional("pss_kb").and_then(Value::as_f64).map(|kb| kb as u64),
+            uss_kb: optional("uss_kb").and_then(Value::as_f64).map(|kb| kb as u64),
+            start_time: optional("start_time").and_then(Value::as_f64).map(|t| t as u64),
+            breakdown: optional("breakdown").map(|b| Breakdown {
+                anon_kb: u64_field(b, "anon_kb"),
+                file_kb: u64_field(b, "file_kb"),
+                shmem_kb: u64_field(b, "shmem_kb"),
+                stack_kb: u64_field(b, "stack_kb"),
+            }),
+            dirty: optional("dirty").map(|d| Dirty { private_kb: u64_field(d, "private_kb"), shared_kb: u64_field(d, "shared_kb") }),
+            numa: optional("numa_kb")
+                .and_then(Value::as_array)
+                .map(|nodes| NodeUsage(nodes.iter().map(|n| n.as_f64().unwrap_or(0.0) as u64).collect())),
+            cgroup: optional("cgroup").and_then(Value::as_str).map(str::to_string),
+            cgroup_memory: optional("cgroup_memory").map(|m| MemoryStat {
+                anon_kb: u64_field(m, "anon_kb"),
+                file_kb: u64_field(m, "file_kb"),
+                kernel_kb: u64_field(m, "kernel_kb"),
+                slab_kb: u64_field(m, "slab_kb"),
+            }),
+            container: optional("container").and_then(Value::as_str).map(str::to_string),
+        }
+    }
+}
+
+/// A non-negative integer field, 0 if missing
+fn u64_field(value: &Value, key: &str) -> u64 {
+    value.get(key).and_then(Value::as_f64).map_or(0, |n| n as u64)
+}
+
+fn breakdown_json(b: Breakdown) -> Value {
+    json::object([
+        ("anon_kb", b.anon_kb.into()),
+        ("file_kb", b.file_kb.into()),
+        ("shmem_kb", b.shmem_kb.into()),
+        ("stack_kb", b.stack_kb.into()),
+    ])
+}
+
+fn dirty_json(d: Dirty) -> Value {
+    json::object([("private_kb", d.private_kb.into()), ("shared_kb", d.shared_kb.into())])
+}
+
+fn memory_stat_json(m: MemoryStat) -> Value {
+    json::object([
+        ("anon_kb", m.anon_kb.into()),
+        ("file_kb", m.file_kb.into()),
+        ("kernel_kb", m.kernel_kb.into()),
+        ("slab_kb", m.slab_kb.into()),
+    ])
+}
+
+/// Memory per node, indexed by node number
+fn numa_json(n: &NodeUsage) -> Value {
+    Value::Array(n.0.iter().map(|kb| (*kb).into()).collect())
+}
+
+impl Report {
+    pub fn new(hostname: String, meminfo: &HashMap<String, u64>, samples: Vec<ProcessSample>) -> Report {
+        let timestamp = crate::procfs::snapshot_timestamp().unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs())
+        });
+        Report {
+            schema_version: SCHEMA_VERSION,
+            timestamp,
+            hostname,
+            mem_total_kb: meminfo.get("MemTotal").copied().unwrap_or(0),
+            hugepages: HugepagePool::from_meminfo(meminfo),
+            pressure: crate::procfs::read_memory_pressure(),
+            dirty_kb: meminfo.get("Dirty").copied().unwrap_or(0),
+            writeback_kb: meminfo.get("Writeback").copied().unwrap_or(0),
+            groups: group_by(samples, |p| p.name.clone()),
+        }
+    }
+
+    /// Serializes the report, including at most limit groups
+    pub fn to_json(&self, limit: usize) -> Value {
+        json::object([
+            ("schema_version", self.schema_version.into()),
+            ("timestamp", self.timestamp.into()),
+            ("hostname", self.hostname.as_str().into()),
+            ("mem_total_kb", self.mem_total_kb.into()),
+            ("hugepages", self.hugepages.to_json()),
+            ("pressure", self.pressure.map(Pressure::to_json).into()),
+            ("dirty_kb", self.dirty_kb.into()),
+            ("writeback_kb", self.writeback_kb.into()),
+            ("total_rss_kb", self.groups.iter().map(|g| g.rss_kb).sum::<u64>().into()),
+            ("groups", Value::Array(self.groups.iter().take(limit).map(Group::to_json).collect())),
+        ])
+    }
+
+    /// Reads a report written with to_json back in
+    pub fn from_json(value: &Value) -> Result<Report, String> {
+        let version = u64_field(value, "schema_version");
+        if version != SCHEMA_VERSION as u64 {
+            return Err(format!("unsupported schema version {version}"));
+        }
+        let groups = value.get("groups").and_then(Value::as_array).ok_or("no groups")?;
+        let samples = groups
+            .iter()
+            .flat_map(|g| g.get("processes").and_then(Value::as_array).unwrap_or_default())
+            .map(ProcessSample::from_json);
+        Ok(Report {
+            schema_version: SCHEMA_VERSION,
+            timestamp: u64_field(value, "timestamp"),
+            hostname: value.get("hostname").and_then(Value::as_str).unwrap_or_default().to_string(),
+            mem_total_kb: u64_field(value, "mem_total_kb"),
+            hugepages: value.get("hugepages").map(HugepagePool::from_json).unwrap_or_default(),
+            pressure: value.get("pressure").filter(|v| !matches!(v, Value::Null)).map(Pressure::from_json),
+            dirty_kb: u64_field(value, "dirty_kb"),
+            writeback_kb: u64_field(value, "writeback_kb"),
+            groups: group_by(samples, |p| p.name.clone()),
+        })
+    }
+
+    /// Combines the reports of several machines into one, grouping processes
+    /// by name across all of them
+    pub fn merge(reports: Vec<Report>) -> Report {
+        let hostnames: Vec<&str> = reports.iter().map(|r| r.hostname.as_str()).collect();
+        let mut merged = Report {
+            schema_version: SCHEMA_VERSION,
+            timestamp: reports.iter().map(|r| r.timestamp).max().unwrap_or(0),
+            hostname: hostnames.join(", "),
+            mem_total_kb: reports.iter().map(|r| r.mem_total_kb).sum(),
+            hugepages: HugepagePool::default(),
+            // Stall times of different machines can't be combined meaningfully
+            pressure: None,
+            dirty_kb: reports.iter().map(|r| r.dirty_kb).sum(),
+            writeback_kb: reports.iter().map(|r| r.writeback_kb).sum(),
+            groups: vec![],
+        };
+        let mut samples = vec![];
+        for report in reports {
+            if merged.hugepages.page_size_kb == 0 || merged.hugepages.page_size_kb == report.hugepages.page_size_kb {
+                merged.hugepages.page_size_kb = report.hugepages.page_size_kb;
+                merged.hugepages.total += report.hugepages.total;
+                merged.hugepages.free += report.hugepages.free;
+                merged.hugepages.reserved += report.hugepages.reserved;
+                merged.hugepages.surplus += report.hugepages.surplus;
+            }
+            samples.extend(report.groups.into_iter().flat_map(|g| g.processes));
+        }
+        merged.groups = group_by(samples, |p| p.name.clone());
+        merged
+    }
+
+    /// Renames processes and groups them again by their new names. Processes
+    /// in containers are renamed by the name before the container's.
+    pub fn rename(&mut self, rename: impl Fn(&str) -> Option<String>) {
+        let samples = std::mem::take(&mut self.groups).into_iter().flat_map(|g| g.processes).map(|mut p| {
+            let suffix = p.container.as_deref().map(|c| container_key("", c)).filter(|s| p.name.ends_with(s.as_str())).unwrap_or_default();
+            if let Some(name) = rename(&p.name[..p.name.len() - suffix.len()]) {
+                p.name = name + &suffix;
+            }
+            p
+        });
+        self.groups = group_by(samples, |p| p.name.clone());
+    }
+
+    pub fn sort(&mut self, key: SortKey) {
+        self.groups.sort_by_key(|g| std::cmp::Reverse(key.value(g)));
+    }
+
+    /// All sampled processes, regardless of group
+    pub fn samples(&self) -> impl Iterator<Item = &ProcessSample> {
+        self.groups.iter().flat_map(|g| g.processes.iter())
+    }
+}
+
+/// Aggregates samples by key, largest first
+pub fn group_by(samples: impl IntoIterator<Item = ProcessSample>, key: impl Fn(&ProcessSample) -> String) -> Vec<Group> {
+    let mut by_key: HashMap<String, Group> = HashMap::new();
+    for p in samples {
+        let group = by_key.entry(key(&p)).or_insert_with_key(|name| Group {
+            name: name.clone(),
+            rss_kb: 0,
+            hugetlb_kb: 0,
+            locked_kb: 0,
+            hwm_kb: 0,
+            peak_kb: 0,
+            swap_kb: 0,
+            threads: 0,
+            gpu_kb: None,
+            pss_kb: None,
+            uss_kb: None,
+            oldest_start: None,
+            newest_start: None,
+            breakdown: None,
+            dirty: None,
+            numa: None,
+            cgroup_memory: None,
+            processes: vec![],
+        });
+        group.rss_kb += p.rss_kb;
+        group.hugetlb_kb += p.hugetlb_kb;
+        group.locked_kb += p.locked_kb;
+        group.hwm_kb += p.hwm_kb;
+        group.peak_kb += p.peak_kb;
+        group.swap_kb += p.swap_kb;
+        group.threads += p.threads;
+        if let Some(kb) = p.gpu_kb {
+            *group.gpu_kb.get_or_insert(0) += kb;
+        }
+        if let Some(kb) = p.pss_kb {
+            *group.pss_kb.get_or_insert(0) += kb;
+        }
+        if let Some(kb) = p.uss_kb {
+            *group.uss_kb.get_or_insert(0) += kb;
+        }
+        if let Some(t) = p.start_time {
+            group.oldest_start = Some(group.oldest_start.map_or(t, |o| o.min(t)));
+            group.newest_start = Some(group.newest_start.map_or(t, |n| n.max(t)));
+        }
+        if let Some(b) = p.breakdown {
+            *group.breakdown.get_or_insert_default() += b;
+        }
+        if let Some(d) = p.dirty {
+            *group.dirty.get_or_insert_default() += d;
+        }
+        if let Some(n) = &p.numa {
+            *group.numa.get_or_insert_default() += n;
+        }
+        group.processes.push(p);
+    }
+
+    let mut groups: Vec<Group> = by_key.into_values().collect();
+    // A cgroup's accounting can only be attributed to a group that has it to itself
+    let mut groups_in: HashMap<String, usize> = HashMap::new();
+    for group in &groups {
+        let cgroups: HashSet<&String> = group.processes.iter().filter_map(|p| p.cgroup.as_ref()).collect();
+        for cgroup in cgroups {
+            *groups_in.entry(cgroup.clone()).or_default() += 1;
+        }
+    }
+    for group in &mut groups {
+        let cgroup = &group.processes[0].cgroup;
+        if cgroup.as_ref().is_some_and(|c| groups_in[c] == 1) && group.processes.iter().all(|p| p.cgroup == *cgroup) {
+            group.cgroup_memory = group.processes[0].cgroup_memory;
+        }
+    }
+    groups.sort_by_key(|g| std::cmp::Reverse(g.rss_kb));
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A process with every optional field set, each numeric field to a
+    /// different value so that mixed up keys are noticed
+    fn full_sample() -> ProcessSample {
+        ProcessSample {
+            pid: 4_000_000,
+            ppid: 2,
+            sid: 3,
+            uid: 4,
+            user: "jürgen".to_string(),
+            name: "say \"hi\" \\ to\tthe\nwörld 🦀".to_string(),
+            rss_kb: 5,
+            hugetlb_kb: 6,
+            locked_kb: 7,
+            hwm_kb: 8,
+            peak_kb: 9,
+            swap_kb: 10,
+            threads: 11,
+            gpu_kb: Some(12),
+            pss_kb: Some(13),
+            uss_kb: Some(14),
+            start_time: Some(1_700_000_000),
+            breakdown: Some(Breakdown { anon_kb: 15, file_kb: 16, shmem_kb: 17, stack_kb: 18 }),
+            dirty: Some(Dirty { private_kb: 19, shared_kb: 20 }),
+            numa: Some(NodeUsage(vec![21, 0, 22])),
+            cgroup: Some("/system.slice/docker-ab\"c.scope".to_string()),
+            cgroup_memory: Some(MemoryStat { anon_kb: 23, file_kb: 24, kernel_kb: 25, slab_kb: 26 }),
+            container: Some("wéb".to_string()),
+        }
+    }
+
+    /// A process where every optional field is None, except for zeros that
+    /// have to stay distinguishable from None
+    fn sparse_sample(pss_kb: Option<u64>) -> ProcessSample {
+        ProcessSample {
+            pid: 30,
+            ppid: 0,
+            sid: 0,
+            uid: 0,
+            user: String::new(),
+            name: "sparse".to_string(),
+            rss_kb: 1,
+            hugetlb_kb: 0,
+            locked_kb: 0,
+            hwm_kb: 0,
+            peak_kb: 0,
+            swap_kb: 0,
+            threads: 1,
+            gpu_kb: None,
+            pss_kb,
+            uss_kb: None,
+            start_time: None,
+            breakdown: None,
+            dirty: None,
+            numa: None,
+            cgroup: None,
+            cgroup_memory: None,
+            container: None,
+        }
+    }
+
+    fn report(samples: Vec<ProcessSample>) -> Report {
+        Report {
+            schema_version: SCHEMA_VERSION,
+            timestamp: 1_700_000_123,
+            hostname: "hôst \"1\"".to_string(),
+            mem_total_kb: 16_000_000,
+            hugepages: HugepagePool { total: 40, free: 41, reserved: 42, surplus: 43, page_size_kb: 2048 },
+            pressure: Some(Pressure { some: [0.5, 1.25, 2.0], full: [0.0, 0.125, 3.5] }),
+            dirty_kb: 44,
+            writeback_kb: 45,
+            groups: group_by(samples, |p| p.name.clone()),
+        }
+    }
+
+    /// Serializes, parses and deserializes the report like --from does
+    fn round_trip(report: &Report) -> Report {
+        let text = report.to_json(usize::MAX).to_pretty_string();
+        Report::from_json(&json::parse(&text).unwrap()).unwrap()
+    }
+
+    fn assert_samples_eq(a: &ProcessSample, b: &ProcessSample) {
+        assert_eq!((a.pid, a.ppid, a.sid, a.uid), (b.pid, b.ppid, b.sid, b.uid));
+        assert_eq!((&a.user, &a.name), (&b.user, &b.name));
+        assert_eq!((a.rss_kb, a.hugetlb_kb, a.locked_kb, a.hwm_kb, a.peak_kb, a.swap_kb, a.threads), (b.rss_kb, b.hugetlb_kb, b.locked_kb, b.hwm_kb, b.peak_kb, b.swap_kb, b.threads));
+        assert_eq!((a.gpu_kb, a.pss_kb, a.uss_kb, a.start_time), (b.gpu_kb, b.pss_kb, b.uss_kb, b.start_time));
+        let breakdown = |p: &ProcessSample| p.breakdown.map(|b| (b.anon_kb, b.file_kb, b.shmem_kb, b.stack_kb));
+        assert_eq!(breakdown(a), breakdown(b));
+        let dirty = |p: &ProcessSample| p.dirty.map(|d| (d.private_kb, d.shared_kb));
+        assert_eq!(dirty(a), dirty(b));
+        assert_eq!(a.numa.as_ref().map(|n| &n.0), b.numa.as_ref().map(|n| &n.0));
+        assert_eq!(a.cgroup, b.cgroup);
+        let memory = |p: &ProcessSample| p.cgroup_memory.map(|m| (m.anon_kb, m.file_kb, m.kernel_kb, m.slab_kb));
+        assert_eq!(memory(a), memory(b));
+        assert_eq!(a.container, b.container);
+    }
+
+    #[test]
+    fn json_round_trip_keeps_every_field() {
+        let original = report(vec![full_sample(), sparse_sample(Some(0)), sparse_sample(None)]);
+        let read = round_trip(&original);
+
+        assert_eq!(read.schema_version, SCHEMA_VERSION);
+        assert_eq!((read.timestamp, read.mem_total_kb, read.dirty_kb, read.writeback_kb), (1_700_000_123, 16_000_000, 44, 45));
+        assert_eq!(read.hostname, original.hostname);
+        let pool = read.hugepages;
+        assert_eq!((pool.total, pool.free, pool.reserved, pool.surplus, pool.page_size_kb), (40, 41, 42, 43, 2048));
+        let pressure = read.pressure.unwrap();
+        assert_eq!((pressure.some, pressure.full), ([0.5, 1.25, 2.0], [0.0, 0.125, 3.5]));
+
+        assert_eq!(read.groups.len(), 2);
+        for (a, b) in original.groups.iter().zip(&read.groups) {
+            assert_eq!(a.name, b.name);
+            assert_eq!(a.num(), b.num());
+            for (a, b) in a.processes.iter().zip(&b.processes) {
+                assert_samples_eq(a, b);
+            }
+        }
+        // Some(0) stays distinct from None
+        let sparse = &read.groups.iter().find(|g| g.name == "sparse").unwrap().processes;
+        assert_eq!(sparse[0].pss_kb, Some(0));
+        assert_eq!(sparse[1].pss_kb, None);
+        // And so do the group totals recomputed from the processes
+        let full = read.groups.iter().find(|g| g.name != "sparse").unwrap();
+        assert_eq!((full.oldest_start, full.newest_start), (Some(1_700_000_000), Some(1_700_000_000)));
+        assert!(full.cgroup_memory.is_some());
+
+        // Nothing that's written is lost
+        assert_eq!(read.to_json(usize::MAX).to_compact_string(), original.to_json(usize::MAX).to_compact_string());
+    }
+
+    #[test]
+    fn json_round_trip_keeps_missing_pressure() {
+        let mut original = report(vec![sparse_sample(None)]);
+        original.pressure = None;
+        assert!(round_trip(&original).pressure.is_none());
+    }
+
+    #[test]
+    fn from_json_rejects_other_schema_versions() {
+        let mut value = report(vec![]).to_json(usize::MAX);
+        if let Value::Object(fields) = &mut value {
+            fields[0].1 = (SCHEMA_VERSION + 1).into();
+        }
+        assert!(Report::from_json(&value).is_err());
+    }
+}
@@ -0,0 +1,376 @@
+//! Derives the application name under which a process is grouped.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::model::ProcessSample;
+use crate::procfs::ProcessFiles;
+use crate::sandbox::sandbox_name;
+
+#[derive(Clone, Copy)]
+pub enum JavaStrategy {
+    Auto,
+    Jar,
+    Main,
+}
+
+impl JavaStrategy {
+    /// The value of --java-by selecting this strategy
+    pub fn name(self) -> &'static str {
+        match self {
+            JavaStrategy::Auto => "auto",
+            JavaStrategy::Jar => "jar",
+            JavaStrategy::Main => "main",
+        }
+    }
+}
+
+/// Where the name a process is grouped by comes from
+#[derive(Clone, Copy)]
+pub enum NameSource {
+    /// argv[0], unless it looks rewritten; scripts are named after the script
+    Auto,
+    /// The basename of argv[0], which processes can change at will
+    Cmdline,
+    /// The basename of the executable; comm where it can't be read
+    Exe,
+    /// The name the kernel keeps, truncated to 15 bytes
+    Comm,
+    /// The full path of the executable, to tell apart installations of the same program
+    Path,
+}
+
+impl NameSource {
+    /// The value of --name-from selecting this source
+    pub fn name(self) -> &'static str {
+        match self {
+            NameSource::Auto => "auto",
+            NameSource::Cmdline => "cmdline",
+            NameSource::Exe => "exe",
+            NameSource::Comm => "comm",
+            NameSource::Path => "path",
+        }
+    }
+}
+
+pub fn parse_name_source(value: &str) -> Option<NameSource> {
+    match value {
+        "auto" => Some(NameSource::Auto),
+        "cmdline" => Some(NameSource::Cmdline),
+        "exe" => Some(NameSource::Exe),
+        "comm" => Some(NameSource::Comm),
+        "path" => Some(NameSource::Path),
+        _ => None,
+    }
+}
+
+pub fn parse_java_strategy(value: &str) -> Option<JavaStrategy> {
+    match value {
+        "auto" => Some(JavaStrategy::Auto),
+        "jar" => Some(JavaStrategy::Jar),
+        "main" => Some(JavaStrategy::Main),
+        _ => None,
+    }
+}
+
+fn find_jar_name(cmdline: &[String]) -> Option<String> {
+    // Looks for "-jar <file>", returns the JAR's basename
+    let mut i = 1; // skip argv[0] ("java")
+    while i < cmdline.len() {
+        let tok = &cmdline[i];
+        if tok == "-jar" {
+            if i + 1 < cmdline.len() {
+                let jar = std::path::Path::new(&cmdline[i + 1]);
+                return jar.file_name().map(|f| f.to_string_lossy().to_string());
+            } else {
+                return None;
+            }
+        }
+        if tok.starts_with('-') {
+            // skip JVM options; handle options with a separate argument
+            if tok == "-cp" || tok == "-classpath" || tok == "--class-path" {
+                i += 2;
+            } else {
+                i += 1;
+            }
+            continue;
+        }
+        break;
+    }
+    None
+}
+
+fn find_main_class(cmdline: &[String]) -> Option<String> {
+    // Skips JVM options to the first non-option token (the main class)
+    let mut i = 1; // skip "java"
+    while i < cmdline.len() && cmdline[i].starts_with('-') {
+        if cmdline[i] == "-cp" || cmdline[i] == "-classpath" || cmdline[i] == "--class-path" {
+            i += 2;
+        } else {
+            i += 1;
+        }
+    }
+    cmdline.get(i).cloned()
+}
+
+/// Try to produce a nicer name for a Java process:
+/// - If "-jar X" is present -> basename(X)
+/// - Else first non-option token after JVM flags -> main class
+fn java_display_name(cmdline: &[String], strat: JavaStrategy) -> Option<String> {
+    match strat {
+        JavaStrategy::Jar => find_jar_name(cmdline),
+        JavaStrategy::Main => find_main_class(cmdline),
+        JavaStrategy::Auto => find_jar_name(cmdline).or_else(|| find_main_class(cmdline)),
+    }
+}
+
+/// Interpreters whose processes are named after the script they run
+const INTERPRETERS: [&str; 9] = ["python", "perl", "ruby", "node", "php", "sh", "bash", "dash", "zsh"];
+
+fn is_interpreter(name: &str) -> bool {
+    // python3.11, perl5.36
+    INTERPRETERS.contains(&name.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.'))
+}
+
+/// The script or module an interpreter runs, if any
+fn find_script(cmdline: &[String]) -> Option<String> {
+    let mut args = cmdline.iter().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            // Inline code
+            "-c" | "-e" | "-E" => return None,
+            // python -m module
+            "-m" => return args.next().cloned(),
+            _ if arg.starts_with('-') => continue,
+            _ => return std::path::Path::new(arg).file_name().map(|f| f.to_string_lossy().to_string()),
+        }
+    }
+    None
+}
+
+/// Whether comm, which the kernel truncates to 15 bytes, matches name
+fn comm_matches(comm: &str, name: &str) -> bool {
+    comm == name || (comm.len() == 15 && name.starts_with(comm))
+}
+
+/// The executable's name, or comm if it can't be read. Reading the command
+/// line of every process is slow on busy hosts, so it is only read for Java,
+/// interpreters and comms that were cut off. argv[0] is the most readable name
+/// then, but daemons rewrite it (e.g. "postgres: checkpointer"); it is only
+/// trusted if it agrees with comm or the executable. Scripts are named after
+/// the interpreter and script.
+fn auto_name(files: &ProcessFiles) -> Option<String> {
+    let comm = files.comm().map(str::to_string);
+    let exe = files.exe_basename();
+    let needs_cmdline = |name: &Option<String>| name.as_deref().is_some_and(|n| n == "java" || n == "javaw" || is_interpreter(n));
+    let truncated = exe.is_none() && comm.as_deref().is_some_and(|c| c.len() == 15);
+    if !needs_cmdline(&comm) && !needs_cmdline(&exe) && !truncated {
+        return exe.or(comm);
+    }
+    let Some(argv0) = files.cmdname() else {
+        return exe.or(comm);
+    };
+    // Login shells start with a dash
+    let argv0 = argv0.trim_start_matches('-').to_string();
+    if is_interpreter(&argv0) {
+        let script = files.cmdline().and_then(find_script);
+        return Some(script.map_or_else(|| argv0.clone(), |s| format!("{argv0}: {s}")));
+    }
+    if comm.as_deref().is_some_and(|c| comm_matches(c, &argv0)) {
+        return Some(argv0);
+    }
+    if exe.as_deref() == Some(argv0.as_str()) {
+        return Some(argv0);
+    }
+    exe.or(comm).or(Some(argv0))
+}
+
+/// Derives the grouping key for a process, or None if it should be skipped.
+/// The command line is only read if the name source or the process needs it.
+pub fn process_key(files: &ProcessFiles, source: NameSource, jstrategy: JavaStrategy) -> Option<String> {
+    // Sandboxed apps are named after their ID, whatever their processes are called
+    if let Some(name) = sandbox_name(files) {
+        return Some(name);
+    }
+    let comm = || files.comm().map(str::to_string);
+    let name = match source {
+        NameSource::Auto => auto_name(files),
+        NameSource::Cmdline => files.cmdname(),
+        NameSource::Exe => files.exe_basename().or_else(comm),
+        NameSource::Comm => comm(),
+        NameSource::Path => files.exe_path().map(str::to_string).or_else(|| {
+            // Without access to the executable, argv[0] is the best guess if it is absolute
+            let argv0 = files.cmdline()?.first()?;
+            if argv0.starts_with('/') { Some(argv0.clone()) } else { comm() }
+        }),
+    };
+    let name = match name {
+        Some(n) if !n.is_empty() => n,
+        _ => return None,
+    };
+
+    let key = if name == "java" || name == "javaw" {
+        let cmdline = files.cmdline().unwrap_or_default();
+        if let Some(app) = java_display_name(cmdline, jstrategy) {
+            let app = app.rsplit('.').next().unwrap_or(&app).to_string();
+            format!("java: {}", app)
+        } else {
+            let exe = files.exe_basename().unwrap_or_else(|| "java".to_string());
+            format!("java ({exe})")
+        }
+    } else {
+        name
+    };
+    Some(key)
+}
+
+/// The name of a process in a Docker, Podman, containerd or CRI-O container;
+/// the same program in different containers is a different service
+pub fn container_key(name: &str, container: &str) -> String {
+    format!("{name} [{container}]")
+}
+
+/// Whether a name was derived by one of the rules above (Java applications,
+/// sandboxes, containers, scripts) rather than taken from the process as is
+pub fn is_derived_name(name: &str) -> bool {
+    match name.split_once(": ") {
+        Some((prefix, _)) => matches!(prefix, "java" | "flatpak" | "snap" | "machine") || is_interpreter(prefix),
+        None => name.starts_with("java ("),
+    }
+}
+
+/// How helper processes are attributed to the application that started them
+#[derive(Clone, Copy)]
+pub enum Rollup {
+    /// To the leader of their session, e.g. a daemon that called setsid()
+    Session,
+    /// To their topmost ancestor that was started by a shell or service manager
+    Launcher,
+}
+
+impl Rollup {
+    /// The value of --rollup selecting this heuristic
+    pub fn name(self) -> &'static str {
+        match self {
+            Rollup::Session => "session",
+            Rollup::Launcher => "launcher",
+        }
+    }
+}
+
+pub fn parse_rollup(value: &str) -> Option<Rollup> {
+    match value {
+        "session" => Some(Rollup::Session),
+        "launcher" => Some(Rollup::Launcher),
+        _ => None,
+    }
+}
+
+/// Programs that start applications rather than being part of them
+const LAUNCHERS: [&str; 19] = [
+    "init", "systemd", "sh", "bash", "dash", "zsh", "fish", "ksh", "tcsh", "csh", "sshd", "login", "su", "sudo", "doas", "runuser",
+    "tmux", "screen", "SCREEN",
+];
+
+fn is_launcher(p: &ProcessSample) -> bool {
+    // Names may be full paths with --name-from=path
+    let name = std::path::Path::new(&p.name).file_name().map_or(p.name.as_str(), |n| n.to_str().unwrap_or_default());
+    p.pid == 1 || p.ppid == 0 || LAUNCHERS.contains(&name)
+}
+
+/// Renames processes after the application root they belong to. Processes
+/// whose parents weren't sampled are their own root.
+pub fn roll_up(samples: &mut [ProcessSample], rollup: Rollup) {
+    let by_pid: HashMap<u32, &ProcessSample> = samples.iter().map(|p| (p.pid, p)).collect();
+    let root = |p: &ProcessSample| -> Option<String> {
+        match rollup {
+            Rollup::Session => by_pid.get(&p.sid).filter(|_| p.sid != p.pid).map(|leader| leader.name.clone()),
+            Rollup::Launcher if is_launcher(p) => None,
+            Rollup::Launcher => {
+                let mut root = p;
+                // The depth limit guards against cycles from pids reused while sampling
+                for _ in 0..64 {
+                    match by_pid.get(&root.ppid) {
+                        Some(parent) if !is_launcher(parent) && parent.pid != root.pid => root = parent,
+                        _ => break,
+                    }
+                }
+                (root.pid != p.pid).then(|| root.name.clone())
+            }
+        }
+    };
+    let names: Vec<Option<String>> = samples.iter().map(root).collect();
+    for (p, name) in samples.iter_mut().zip(names) {
+        if let Some(name) = name {
+            p.name = name;
+        }
+    }
+}
+
+/// Merges processes of several names into one application, e.g. chrome and
+/// chrome_crashpad into "Chrome"
+#[derive(Default)]
+pub struct Aliases {
+    /// Application name by process name
+    names: HashMap<String, String>,
+}
+
+impl Aliases {
+    /// Adds a rule of the form "Name = process, process, ..."
+    pub fn add(&mut self, rule: &str) -> Result<(), String> {
+        let (name, members) = rule.split_once('=').ok_or("expected NAME=PROCESS,...")?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("missing application name".to_string());
+        }
+        let mut any = false;
+        for member in members.split(',').map(str::trim).filter(|m| !m.is_empty()) {
+            self.names.insert(member.to_string(), name.to_string());
+            any = true;
+        }
+        if !any {
+            return Err(format!("no process names for {name}"));
+        }
+        Ok(())
+    }
+
+    /// Adds the rules in the user's config file, one per line, if it exists.
+    /// Rules added before, i.e. from the command line, take precedence.
+    pub fn load_config(&mut self) -> Result<(), String> {
+        let Some(path) = config_path() else {
+            return Ok(());
+        };
+        let Ok(text) = std::fs::read_to_string(&path) else {
+            return Ok(());
+        };
+        let mut config = Aliases::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if !line.is_empty() {
+                config.add(line).map_err(|e| format!("{}:{}: {e}", path.display(), i + 1))?;
+            }
+        }
+        for (member, name) in config.names {
+            self.names.entry(member).or_insert(name);
+        }
+        Ok(())
+    }
+
+    /// The application name for a process name, if it has an alias
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.names.get(name).map(String::as_str)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+/// $XDG_CONFIG_HOME/memory/aliases, or ~/.config/memory/aliases
+fn config_path() -> Option<PathBuf> {
+    let dir = match std::env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("memory").join("aliases"))
+}
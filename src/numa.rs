@@ -0,0 +1,57 @@
+//! Reads the NUMA node placement of process memory from /proc/[pid]/numa_maps.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use crate::procfs;
+
+/// Resident memory per NUMA node in kB, indexed by node number
+#[derive(Clone, Default)]
+pub struct NodeUsage(pub Vec<u64>);
+
+impl NodeUsage {
+    fn add(&mut self, node: usize, kb: u64) {
+        if self.0.len() <= node {
+            self.0.resize(node + 1, 0);
+        }
+        self.0[node] += kb;
+    }
+
+    pub fn node_kb(&self, node: usize) -> u64 {
+        self.0.get(node).copied().unwrap_or(0)
+    }
+}
+
+impl std::ops::AddAssign<&NodeUsage> for NodeUsage {
+    fn add_assign(&mut self, other: &NodeUsage) {
+        for (node, kb) in other.0.iter().enumerate() {
+            self.add(node, *kb);
+        }
+    }
+}
+
+pub fn read_numa_maps(pid: &str) -> Option<NodeUsage> {
+    let file = File::open(procfs::path(&format!("/proc/{pid}/numa_maps"))).ok()?;
+    let mut usage = NodeUsage::default();
+    for line in BufReader::new(file).lines().map_while(Result::ok) {
+        // Each line lists the pages of one mapping per node as N<node>=<pages>
+        let mut pages: Vec<(usize, u64)> = vec![];
+        let mut page_kb = 4;
+        for field in line.split_whitespace() {
+            let Some((key, value)) = field.split_once('=') else {
+                continue;
+            };
+            if key == "kernelpagesize_kB" {
+                page_kb = value.parse().unwrap_or(4);
+            } else if let Some(node) = key.strip_prefix('N')
+                && let (Ok(node), Ok(n)) = (node.parse(), value.parse())
+            {
+                pages.push((node, n));
+            }
+        }
+        for (node, n) in pages {
+            usage.add(node, n * page_kb);
+        }
+    }
+    Some(usage)
+}
@@ -0,0 +1,76 @@
+//! The `oom` subcommand: which processes the OOM killer would pick first.
+//!
+//! The kernel kills the process with the highest oom_score, which is derived
+//! from its memory usage and adjusted by oom_score_adj (-1000 to 1000). A
+//! negative adjustment protects a process; -1000 exempts it entirely.
+
+use std::fs;
+use std::io::{self, Write};
+
+use crate::model::Report;
+use crate::procfs;
+
+struct OomEntry<'a> {
+    pid: u32,
+    name: &'a str,
+    user: &'a str,
+    rss_kb: u64,
+    score: i32,
+    adj: i32,
+}
+
+fn read_int(pid: u32, file: &str) -> Option<i32> {
+    fs::read_to_string(procfs::path(&format!("/proc/{pid}/{file}"))).ok()?.trim().parse().ok()
+}
+
+pub fn print_oom(out: &mut dyn Write, report: &Report, limit: usize) -> io::Result<()> {
+    let mut entries: Vec<OomEntry> = report
+        .samples()
+        .filter_map(|p| {
+            Some(OomEntry {
+                pid: p.pid,
+                name: &p.name,
+                user: &p.user,
+                rss_kb: p.rss_kb,
+                score: read_int(p.pid, "oom_score")?,
+                adj: read_int(p.pid, "oom_score_adj")?,
+            })
+        })
+        .collect();
+    entries.sort_by_key(|e| (std::cmp::Reverse(e.score), std::cmp::Reverse(e.rss_kb)));
+
+    writeln!(out, "{:>7} {:<35} {:<12} {:>12} {:>6} {:>6}", "PID", "Application", "User", "Memory(MB)", "Score", "Adj")?;
+    for e in entries.iter().take(limit) {
+        writeln!(
+            out,
+            "{:>7} {:<35} {:<12} {:>12.2} {:>6} {:>6}",
+            e.pid,
+            e.name,
+            e.user,
+            e.rss_kb as f64 / 1024.0,
+            e.score,
+            e.adj
+        )?;
+    }
+
+    let mut protected: Vec<&OomEntry> = entries.iter().filter(|e| e.adj < 0).collect();
+    if !protected.is_empty() {
+        protected.sort_by_key(|e| std::cmp::Reverse(e.rss_kb));
+        writeln!(out)?;
+        writeln!(out, "Protected by a negative oom_score_adj:")?;
+        for e in protected.into_iter().take(limit) {
+            let note = if e.adj == -1000 { "never killed" } else { "less likely to be killed" };
+            writeln!(
+                out,
+                "{:>7} {:<35} {:<12} {:>12.2} {:>6} {:>6}  {note}",
+                e.pid,
+                e.name,
+                e.user,
+                e.rss_kb as f64 / 1024.0,
+                e.score,
+                e.adj
+            )?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,316 @@
+//! Renders a report in the supported output formats.
+
+use std::io::{self, Write};
+
+use crate::model::{Group, Report, group_by};
+use crate::treemap;
+
+/// What a row's bar length is relative to
+#[derive(Clone, Copy, PartialEq)]
+pub enum BarScale {
+    Largest,
+    Total,
+}
+
+/// Which optional columns to show in addition to the default ones
+#[derive(Default)]
+pub struct Columns {
+    /// PSS, USS and swap next to the resident memory
+    pub metrics: bool,
+    pub threads: bool,
+    pub breakdown: bool,
+    /// The kernel's accounting of the cgroup a group runs in
+    pub cgroup_memory: bool,
+    pub hugepages: bool,
+    pub locked: bool,
+    pub dirty: bool,
+    pub peak: bool,
+    pub numa: bool,
+    pub gpu: bool,
+    pub age: bool,
+}
+
+/// An optional column, computed from a group
+struct Column {
+    header: String,
+    width: usize,
+    value: Box<dyn Fn(&Group) -> String>,
+}
+
+impl Column {
+    fn new(header: &str, width: usize, value: impl Fn(&Group) -> String + 'static) -> Column {
+        Column { header: header.to_string(), width, value: Box::new(value) }
+    }
+}
+
+fn mb(kb: u64) -> String {
+    format!("{:.2}", kb as f64 / 1024.0)
+}
+
+/// A duration in its two largest units, e.g. "3d4h" or "12m"
+pub fn age(secs: u64) -> String {
+    let (days, hours, mins) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60);
+    match (days, hours, mins) {
+        (0, 0, 0) => format!("{secs}s"),
+        (0, 0, m) => format!("{m}m"),
+        (0, h, m) => format!("{h}h{m}m"),
+        (d, h, _) => format!("{d}d{h}h"),
+    }
+}
+
+fn extra_columns(columns: &Columns, report: &Report) -> Vec<Column> {
+    let mut cols = vec![];
+    if columns.metrics {
+        cols.extend([
+            Column::new("PSS(MB)", 10, |g| g.pss_kb.map_or(String::new(), mb)),
+            Column::new("USS(MB)", 10, |g| g.uss_kb.map_or(String::new(), mb)),
+            Column::new("Swap(MB)", 10, |g| mb(g.swap_kb)),
+        ]);
+    }
+    if columns.threads {
+        cols.push(Column::new("Threads", 8, |g| g.threads.to_string()));
+    }
+    if columns.breakdown {
+        cols.extend([
+            Column::new("Anon(MB)", 10, |g| g.breakdown.map_or(String::new(), |b| mb(b.anon_kb))),
+            Column::new("File(MB)", 10, |g| g.breakdown.map_or(String::new(), |b| mb(b.file_kb))),
+            Column::new("Shmem(MB)", 10, |g| g.breakdown.map_or(String::new(), |b| mb(b.shmem_kb))),
+            Column::new("Stack(MB)", 10, |g| g.breakdown.map_or(String::new(), |b| mb(b.stack_kb))),
+        ]);
+    }
+    if columns.cgroup_memory {
+        cols.extend([
+            Column::new("CgAnon(MB)", 11, |g| g.cgroup_memory.map_or(String::new(), |m| mb(m.anon_kb))),
+            Column::new("CgFile(MB)", 11, |g| g.cgroup_memory.map_or(String::new(), |m| mb(m.file_kb))),
+            Column::new("CgKernel(MB)", 13, |g| g.cgroup_memory.map_or(String::new(), |m| mb(m.kernel_kb))),
+            Column::new("CgSlab(MB)", 11, |g| g.cgroup_memory.map_or(String::new(), |m| mb(m.slab_kb))),
+        ]);
+    }
+    if columns.hugepages {
+        cols.push(Column::new("Huge(MB)", 10, |g| mb(g.hugetlb_kb)));
+    }
+    if columns.locked {
+        cols.push(Column::new("Locked(MB)", 10, |g| mb(g.locked_kb)));
+    }
+    if columns.gpu {
+        cols.push(Column::new("GPU(MB)", 10, |g| g.gpu_kb.map_or(String::new(), mb)));
+    }
+    if columns.age {
+        // Ages as of when the report was taken, so that snapshots show them as they were
+        let now = report.timestamp;
+        cols.extend([
+            Column::new("Oldest", 8, move |g| g.oldest_start.map_or(String::new(), |t| age(now.saturating_sub(t)))),
+            Column::new("Newest", 8, move |g| g.newest_start.map_or(String::new(), |t| age(now.saturating_sub(t)))),
+        ]);
+    }
+    if columns.peak {
+        cols.extend([
+            Column::new("HWM(MB)", 10, |g| mb(g.hwm_kb)),
+            Column::new("Peak(MB)", 10, |g| mb(g.peak_kb)),
+        ]);
+    }
+    if columns.numa {
+        let nodes = report.groups.iter().filter_map(|g| g.numa.as_ref()).map(|n| n.0.len()).max().unwrap_or(0);
+        for node in 0..nodes {
+            let value = move |g: &Group| g.numa.as_ref().map_or(String::new(), |n| mb(n.node_kb(node)));
+            cols.push(Column::new(&format!("N{node}(MB)"), 10, value));
+        }
+    }
+    if columns.dirty {
+        cols.extend([
+            Column::new("PrivDirty(MB)", 13, |g| g.dirty.map_or(String::new(), |d| mb(d.private_kb))),
+            Column::new("ShrDirty(MB)", 12, |g| g.dirty.map_or(String::new(), |d| mb(d.shared_kb))),
+        ]);
+    }
+    cols
+}
+
+/// Summarizes the hugepage pool below the table
+fn print_hugepage_pool(out: &mut dyn Write, report: &Report) -> io::Result<()> {
+    let pool = &report.hugepages;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "Hugepages ({} kB): {} total, {} in use, {} free, {} reserved but unused, {} surplus",
+        pool.page_size_kb,
+        pool.total,
+        pool.used(),
+        pool.free.saturating_sub(pool.reserved),
+        pool.reserved,
+        pool.surplus
+    )
+}
+
+/// The system-wide lines below the table for the columns that have one
+fn print_summary(out: &mut dyn Write, report: &Report, columns: &Columns) -> io::Result<()> {
+    if columns.hugepages {
+        print_hugepage_pool(out, report)?;
+    }
+    if columns.dirty {
+        writeln!(out)?;
+        writeln!(
+            out,
+            "System: {:.2} MB dirty, {:.2} MB under writeback",
+            report.dirty_kb as f64 / 1024.0,
+            report.writeback_kb as f64 / 1024.0
+        )?;
+    }
+    Ok(())
+}
+
+const BAR_WIDTH: usize = 30;
+
+/// Draws a bar of up to BAR_WIDTH cells using eighth-block characters for sub-cell precision
+fn bar(fraction: f64) -> String {
+    const PARTIAL: [char; 8] = [' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+    let eighths = (fraction.clamp(0.0, 1.0) * (BAR_WIDTH * 8) as f64).round() as usize;
+    let mut s = "█".repeat(eighths / 8);
+    let rest = eighths % 8;
+    if rest > 0 {
+        s.push(PARTIAL[rest]);
+    }
+    s
+}
+
+/// Formats the pressure averages, highlighted in yellow or red when high
+fn pressure_avgs(avgs: [f64; 3], warn: f64, crit: f64, color: bool) -> String {
+    let text = format!("{:.2} {:.2} {:.2}", avgs[0], avgs[1], avgs[2]);
+    match avgs[0] {
+        v if color && v >= crit => format!("\x1b[31m{text}\x1b[0m"),
+        v if color && v >= warn => format!("\x1b[33m{text}\x1b[0m"),
+        _ => text,
+    }
+}
+
+/// Prints the memory pressure above the table, if the kernel supports PSI
+pub fn print_pressure(out: &mut dyn Write, report: &Report, color: bool) -> io::Result<()> {
+    let Some(p) = report.pressure else {
+        return Ok(());
+    };
+    writeln!(
+        out,
+        "Memory pressure (avg10 avg60 avg300): some {}, full {}",
+        pressure_avgs(p.some, 10.0, 40.0, color),
+        pressure_avgs(p.full, 5.0, 20.0, color)
+    )?;
+    writeln!(out)
+}
+
+/// A group's processes split up by user, for sub-rows below the group
+fn by_user(group: &Group) -> Vec<Group> {
+    group_by(group.processes.iter().cloned(), |p| p.user.clone())
+}
+
+pub fn print_table(
+    out: &mut dyn Write,
+    report: &Report,
+    limit: usize,
+    columns: &Columns,
+    bars: Option<BarScale>,
+    nest_users: bool,
+) -> io::Result<()> {
+    let total_kb = report.mem_total_kb;
+    let extra = extra_columns(columns, report);
+    write!(out, "{:<35} {:>4} {:>12} {:>8} {:>8}", "Application", "Num", "Memory(MB)", "%", "Cum.%")?;
+    for col in &extra {
+        write!(out, " {:>w$}", col.header, w = col.width)?;
+    }
+    writeln!(out)?;
+    let scale = match bars {
+        Some(BarScale::Total) => total_kb,
+        _ => report.groups.iter().map(|g| g.rss_kb).max().unwrap_or(0),
+    };
+    let mut cum = 0.0_f64;
+    for group in report.groups.iter().take(limit) {
+        let mb = (group.rss_kb as f64) / 1024.0;
+        let pct = (group.rss_kb as f64) * 100.0 / (total_kb as f64);
+        cum += pct;
+        write!(out, "{:<35} {:>4} {:>12.2} {:>7.2}% {:>7.2}%", group.name, group.num(), mb, pct, cum)?;
+        for col in &extra {
+            write!(out, " {:>w$}", (col.value)(group), w = col.width)?;
+        }
+        if bars.is_some() && scale > 0 {
+            write!(out, " {}", bar(group.rss_kb as f64 / scale as f64))?;
+        }
+        writeln!(out)?;
+        for user in by_user(group).iter().filter(|_| nest_users) {
+            let mb = (user.rss_kb as f64) / 1024.0;
+            let pct = (user.rss_kb as f64) * 100.0 / (total_kb as f64);
+            let mut row = format!("{:<35} {:>4} {:>12.2} {:>7.2}% {:>8}", format!("  └ {}", user.name), user.num(), mb, pct, "");
+            for col in &extra {
+                row += &format!(" {:>w$}", (col.value)(user), w = col.width);
+            }
+            // Without columns after it, the empty Cum.% column would leave trailing spaces
+            writeln!(out, "{}", row.trim_end())?;
+        }
+    }
+    print_summary(out, report, columns)
+}
+
+/// Prints the table in GitHub-flavored Markdown
+pub fn print_markdown(out: &mut dyn Write, report: &Report, limit: usize, columns: &Columns, nest_users: bool) -> io::Result<()> {
+    let total_kb = report.mem_total_kb;
+    let extra = extra_columns(columns, report);
+    write!(out, "| Application | Num | Memory (MB) | % | Cum. % |")?;
+    for col in &extra {
+        write!(out, " {} |", col.header)?;
+    }
+    write!(out, "\n|:--|--:|--:|--:|--:|")?;
+    for _ in &extra {
+        write!(out, "--:|")?;
+    }
+    writeln!(out)?;
+    let mut cum = 0.0_f64;
+    for group in report.groups.iter().take(limit) {
+        let mb = (group.rss_kb as f64) / 1024.0;
+        let pct = (group.rss_kb as f64) * 100.0 / (total_kb as f64);
+        cum += pct;
+        let name = group.name.replace('|', "\\|");
+        write!(out, "| {} | {} | {:.2} | {:.2}% | {:.2}% |", name, group.num(), mb, pct, cum)?;
+        for col in &extra {
+            write!(out, " {} |", (col.value)(group))?;
+        }
+        writeln!(out)?;
+        for user in by_user(group).iter().filter(|_| nest_users) {
+            let mb = (user.rss_kb as f64) / 1024.0;
+            let pct = (user.rss_kb as f64) * 100.0 / (total_kb as f64);
+            write!(out, "| └ {} | {} | {:.2} | {:.2}% | |", user.name.replace('|', "\\|"), user.num(), mb, pct)?;
+            for col in &extra {
+                write!(out, " {} |", (col.value)(user))?;
+            }
+            writeln!(out)?;
+        }
+    }
+    print_summary(out, report, columns)
+}
+
+pub fn print_json(out: &mut dyn Write, report: &Report, limit: usize) -> io::Result<()> {
+    out.write_all(report.to_json(limit).to_pretty_string().as_bytes())
+}
+
+pub fn write_treemap(out: &mut dyn Write, report: &Report, limit: usize, nest_users: bool) -> io::Result<()> {
+    let nodes: Vec<treemap::Node> = if nest_users {
+        let mut users: Vec<treemap::Node> = group_by(report.samples().cloned(), |p| p.user.clone())
+            .into_iter()
+            .take(limit)
+            .map(|user| {
+                let children: Vec<treemap::Node> =
+                    group_by(user.processes, |p| p.name.clone()).into_iter().take(limit).map(|g| treemap::Node::leaf(g.name, g.rss_kb)).collect();
+                // Only what is drawn inside a user counts towards its area
+                let kb = children.iter().map(|c| c.kb).sum();
+                treemap::Node { label: user.name, kb, children }
+            })
+            .collect();
+        users.sort_by_key(|u| std::cmp::Reverse(u.kb));
+        users
+    } else {
+        report
+            .groups
+            .iter()
+            .take(limit)
+            .map(|g| treemap::Node::leaf(g.name.clone(), g.rss_kb))
+            .collect()
+    };
+
+    out.write_all(treemap::render(&nodes).as_bytes())
+}
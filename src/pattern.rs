@@ -0,0 +1,256 @@
+//! A small regular expression matcher for selecting groups by name, supporting
+//! the common subset: literals, `.`, `[...]` and `[^...]` classes, the
+//! quantifiers `*`, `+` and `?`, the anchors `^` and `$`, alternation with `|`
+//! and escaping with `\`, e.g. `\[` for the brackets around container names.
+//! Groups with parentheses are not supported.
+
+enum Atom {
+    Any,
+    Char(char),
+    /// Characters and ranges, negated if the flag is set
+    Class(Vec<(char, char)>, bool),
+}
+
+impl Atom {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Atom::Any => true,
+            Atom::Char(a) => *a == c,
+            Atom::Class(ranges, negated) => ranges.iter().any(|(lo, hi)| (*lo..=*hi).contains(&c)) != *negated,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Repeat {
+    One,
+    /// ?
+    Optional,
+    /// *
+    Any,
+    /// +
+    Many,
+}
+
+struct Branch {
+    anchored_start: bool,
+    anchored_end: bool,
+    items: Vec<(Atom, Repeat)>,
+}
+
+pub struct Pattern {
+    branches: Vec<Branch>,
+}
+
+impl Pattern {
+    pub fn parse(pattern: &str) -> Result<Pattern, String> {
+        let branches = split_alternatives(pattern).into_iter().map(parse_branch).collect::<Result<_, _>>()?;
+        Ok(Pattern { branches })
+    }
+
+    /// Whether the pattern matches anywhere in text
+    pub fn is_match(&self, text: &str) -> bool {
+        let chars: Vec<char> = text.chars().collect();
+        self.branches.iter().any(|b| {
+            // Shared by all start positions, as the rest of a match doesn't depend on where it began
+            let mut failed = vec![false; b.items.len() * (chars.len() + 1)];
+            let starts = if b.anchored_start { 0..=0 } else { 0..=chars.len() };
+            starts.into_iter().any(|start| match_here(&b.items, 0, &chars, start, b.anchored_end, &mut failed))
+        })
+    }
+}
+
+/// Splits at unescaped | outside of character classes
+fn split_alternatives(pattern: &str) -> Vec<String> {
+    let mut branches = vec![String::new()];
+    let (mut escaped, mut in_class) = (false, false);
+    for c in pattern.chars() {
+        match c {
+            '|' if !escaped && !in_class => {
+                branches.push(String::new());
+                continue;
+            }
+            '[' if !escaped => in_class = true,
+            ']' if !escaped => in_class = false,
+            _ => {}
+        }
+        escaped = c == '\\' && !escaped;
+        if let Some(branch) = branches.last_mut() {
+            branch.push(c);
+        }
+    }
+    branches
+}
+
+fn parse_branch(pattern: String) -> Result<Branch, String> {
+    let mut chars = pattern.chars().peekable();
+    let anchored_start = chars.next_if_eq(&'^').is_some();
+    let mut anchored_end = false;
+    let mut items: Vec<(Atom, Repeat)> = vec![];
+    while let Some(c) = chars.next() {
+        let atom = match c {
+            '.' => Atom::Any,
+            '$' if chars.peek().is_none() => {
+                anchored_end = true;
+                break;
+            }
+            '\\' => Atom::Char(chars.next().ok_or("trailing backslash")?),
+            '[' => {
+                let negated = chars.next_if_eq(&'^').is_some();
+                let mut ranges = vec![];
+                loop {
+                    let lo = match chars.next().ok_or("unterminated [")? {
+                        ']' if !ranges.is_empty() => break,
+                        '\\' => chars.next().ok_or("trailing backslash")?,
+                        c => c,
+                    };
+                    let hi = if chars.peek() == Some(&'-') && chars.clone().nth(1).is_some_and(|c| c != ']') {
+                        chars.next();
+                        chars.next().unwrap_or(lo)
+                    } else {
+                        lo
+                    };
+                    ranges.push((lo, hi));
+                }
+                Atom::Class(ranges, negated)
+            }
+            '*' | '+' | '?' => return Err(format!("nothing to repeat before {c}")),
+            '(' | ')' => return Err("groups are not supported".to_string()),
+            c => Atom::Char(c),
+        };
+        let repeat = match chars.peek() {
+            Some('*') => Repeat::Any,
+            Some('+') => Repeat::Many,
+            Some('?') => Repeat::Optional,
+            _ => Repeat::One,
+        };
+        if repeat != Repeat::One {
+            chars.next();
+        }
+        items.push((atom, repeat));
+    }
+    Ok(Branch { anchored_start, anchored_end, items })
+}
+
+/// Backtracking match of the items from index item on at position pos of
+/// text. Every (item, pos) that failed is recorded in failed, so that each is
+/// tried once and patterns such as `a*a*a*b` take polynomial time.
+fn match_here(items: &[(Atom, Repeat)], item: usize, text: &[char], pos: usize, anchored_end: bool, failed: &mut [bool]) -> bool {
+    let Some((atom, repeat)) = items.get(item) else {
+        return !anchored_end || pos == text.len();
+    };
+    let key = item * (text.len() + 1) + pos;
+    if failed[key] {
+        return false;
+    }
+    let (min, max) = match repeat {
+        Repeat::One => (1, 1),
+        Repeat::Optional => (0, 1),
+        Repeat::Any => (0, usize::MAX),
+        Repeat::Many => (1, usize::MAX),
+    };
+    // Longest run of matching characters, then try shorter ones
+    let run = text[pos..].iter().take(max).take_while(|c| atom.matches(**c)).count();
+    let matched = (min..=run).rev().any(|n| match_here(items, item + 1, text, pos + n, anchored_end, failed));
+    failed[key] = !matched;
+    matched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        Pattern::parse(pattern).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn matches_literals_anywhere() {
+        assert!(matches("fox", "firefox"));
+        assert!(matches("", "anything"));
+        assert!(matches("", ""));
+        assert!(!matches("fox", "fo"));
+        assert!(matches("日本", "Grüße aus 日本"));
+    }
+
+    #[test]
+    fn matches_anchors() {
+        assert!(matches("^fire", "firefox"));
+        assert!(!matches("^fox", "firefox"));
+        assert!(matches("fox$", "firefox"));
+        assert!(!matches("fire$", "firefox"));
+        assert!(matches("^firefox$", "firefox"));
+        assert!(!matches("^fire$", "firefox"));
+        // Only a trailing $ anchors
+        assert!(matches("a$b", "a$b"));
+    }
+
+    #[test]
+    fn matches_quantifiers() {
+        assert!(matches("^ab*c$", "ac"));
+        assert!(matches("^ab*c$", "abbbc"));
+        assert!(!matches("^ab+c$", "ac"));
+        assert!(matches("^ab+c$", "abc"));
+        assert!(matches("^ab?c$", "ac"));
+        assert!(matches("^ab?c$", "abc"));
+        assert!(!matches("^ab?c$", "abbc"));
+        assert!(matches("^.*$", ""));
+        assert!(matches("^a.c$", "a-c"));
+        assert!(!matches("^a.c$", "ac"));
+        // Backtracking gives characters back to later items
+        assert!(matches("^a*ab$", "aaab"));
+    }
+
+    #[test]
+    fn matches_classes() {
+        assert!(matches("^[a-c]+$", "abcabc"));
+        assert!(!matches("^[a-c]+$", "abcd"));
+        assert!(matches("^[^0-9]+$", "abc"));
+        assert!(!matches("^[^0-9]+$", "ab1"));
+        assert!(matches("^[xa-cz]$", "z"));
+        // ] right after [ and - at the edges are literal
+        assert!(matches("^[]]$", "]"));
+        assert!(matches("^[a-]+$", "a-"));
+        assert!(matches("^[-a]+$", "-a"));
+        assert!(matches(r"^[\]x]+$", "]x"));
+        // No alternation inside a class
+        assert!(matches("^[a|b]$", "|"));
+    }
+
+    #[test]
+    fn matches_escapes() {
+        assert!(matches(r"\[web\]$", "nginx [web]"));
+        assert!(!matches(r"^a\.c$", "abc"));
+        assert!(matches(r"^a\.c$", "a.c"));
+        assert!(matches(r"^a\*$", "a*"));
+        assert!(matches(r"^a\|b$", "a|b"));
+        assert!(matches(r"^\\$", "\\"));
+    }
+
+    #[test]
+    fn matches_alternatives() {
+        let pattern = Pattern::parse("^chrome$|fire|fox$").unwrap();
+        assert!(pattern.is_match("chrome"));
+        assert!(!pattern.is_match("chromium"));
+        assert!(pattern.is_match("campfire"));
+        assert!(pattern.is_match("firefox"));
+        assert!(!pattern.is_match("foxes"));
+        assert!(matches("^a|$", "b"));
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        for pattern in ["*a", "a|+b", "a\\", "[a", "[^", "(a)", "a)", "[a\\"] {
+            assert!(Pattern::parse(pattern).is_err(), "{pattern:?} should not parse");
+        }
+    }
+
+    #[test]
+    fn nested_quantifiers_take_polynomial_time() {
+        // Exponential without remembering failed positions
+        let pattern = Pattern::parse(&format!("{}b", "a*".repeat(20))).unwrap();
+        let text = "a".repeat(1000);
+        assert!(!pattern.is_match(&text));
+        assert!(pattern.is_match(&format!("{text}b")));
+    }
+}
@@ -0,0 +1,182 @@
+//! The `pid` subcommand: the memory of one process by mapping, for when the
+//! grouped report has pointed at it.
+//!
+//! Everything is read from /proc/[pid]/smaps, which lists every mapping with
+//! its resident, proportional and swapped out size, and whether transparent
+//! huge pages back it or could.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+
+use crate::naming::{JavaStrategy, NameSource, container_key, process_key};
+use crate::procfs::{self, ProcessFiles};
+use crate::sandbox::container_name;
+use crate::smaps::{Mapping, read_smaps};
+
+/// What kind of memory a mapping holds, in the order they are listed
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Category {
+    Heap,
+    Stack,
+    Anonymous,
+    Shmem,
+    File,
+    Other,
+}
+
+impl Category {
+    fn of(m: &Mapping) -> Category {
+        let p = m.pathname.as_str();
+        if p == "[heap]" {
+            Category::Heap
+        } else if p.starts_with("[stack") {
+            Category::Stack
+        } else if m.is_shmem() {
+            Category::Shmem
+        } else if m.is_file() {
+            Category::File
+        } else if p.is_empty() || p.starts_with("[anon:") {
+            // Named with prctl(PR_SET_VMA_ANON_NAME), e.g. by Android and jemalloc
+            Category::Anonymous
+        } else {
+            // [vdso], [vvar] and other pages the kernel maps
+            Category::Other
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Category::Heap => "Heap",
+            Category::Stack => "Stack",
+            Category::Anonymous => "Anonymous",
+            Category::Shmem => "Shared memory",
+            Category::File => "Files",
+            Category::Other => "Other",
+        }
+    }
+}
+
+/// Sizes summed over several mappings
+#[derive(Default)]
+struct Totals {
+    mappings: usize,
+    size_kb: u64,
+    rss_kb: u64,
+    pss_kb: u64,
+    swap_kb: u64,
+    /// In transparent huge pages and hugetlbfs
+    huge_kb: u64,
+}
+
+impl Totals {
+    fn add(&mut self, m: &Mapping) {
+        self.mappings += 1;
+        self.size_kb += m.size_kb;
+        self.rss_kb += m.rss_kb;
+        self.pss_kb += m.pss_kb;
+        self.swap_kb += m.swap_kb;
+        self.huge_kb += m.anon_huge_kb + m.hugetlb_kb;
+    }
+}
+
+fn mb(kb: u64) -> f64 {
+    kb as f64 / 1024.0
+}
+
+/// The system's transparent huge page mode, e.g. "madvise", which decides
+/// whether eligible mappings actually get huge pages
+fn thp_mode() -> Option<String> {
+    let enabled = fs::read_to_string(procfs::path("/sys/kernel/mm/transparent_hugepage/enabled")).ok()?;
+    let (_, rest) = enabled.split_once('[')?;
+    Some(rest.split_once(']')?.0.to_string())
+}
+
+fn print_totals(out: &mut dyn Write, name: &str, t: &Totals) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:<15} {:>8} {:>12.2} {:>10.2} {:>10.2} {:>10.2} {:>10.2}",
+        name,
+        t.mappings,
+        mb(t.size_kb),
+        mb(t.rss_kb),
+        mb(t.pss_kb),
+        mb(t.swap_kb),
+        mb(t.huge_kb)
+    )
+}
+
+/// Prints the totals of each kind of mapping, the limit largest mappings and
+/// how huge pages are used
+pub fn print_process(out: &mut dyn Write, pid: u32, name_from: NameSource, java: JavaStrategy, limit: usize) -> io::Result<()> {
+    let files = ProcessFiles::new(&pid.to_string());
+    let Some(mut mappings) = read_smaps(&files.pid) else {
+        if fs::metadata(procfs::path(&format!("/proc/{pid}"))).is_err() {
+            eprintln!("No process with pid {pid}");
+        } else {
+            eprintln!("Could not read the mappings of process {pid}; those of other users' processes need root");
+        }
+        procfs::exit(1);
+    };
+
+    let mut name = process_key(&files, name_from, java).unwrap_or_default();
+    if let Some(container) = container_name(&files) {
+        name = container_key(&name, &container);
+    }
+    writeln!(out, "Process {pid}: {name}")?;
+    if let Some(cmdline) = files.cmdline().filter(|c| !c.is_empty()) {
+        writeln!(out, "Command line: {}", cmdline.join(" "))?;
+    }
+
+    let mut categories: BTreeMap<Category, Totals> = BTreeMap::new();
+    let mut total = Totals::default();
+    for m in &mappings {
+        categories.entry(Category::of(m)).or_default().add(m);
+        total.add(m);
+    }
+    writeln!(out)?;
+    writeln!(
+        out,
+        "{:<15} {:>8} {:>12} {:>10} {:>10} {:>10} {:>10}",
+        "Category", "Mappings", "Virtual(MB)", "RSS(MB)", "PSS(MB)", "Swap(MB)", "Huge(MB)"
+    )?;
+    for (category, totals) in &categories {
+        print_totals(out, category.name(), totals)?;
+    }
+    print_totals(out, "Total", &total)?;
+
+    mappings.sort_by_key(|m| std::cmp::Reverse(m.rss_kb + m.swap_kb + m.hugetlb_kb));
+    writeln!(out)?;
+    writeln!(out, "{:<33} {:<5} {:>10} {:>10} {:>10} {:>10}  Pathname", "Address", "Perms", "RSS(MB)", "PSS(MB)", "Swap(MB)", "Huge(MB)")?;
+    for m in mappings.iter().take(limit) {
+        let pathname = if m.pathname.is_empty() { "[anon]" } else { &m.pathname };
+        writeln!(
+            out,
+            "{:<33} {:<5} {:>10.2} {:>10.2} {:>10.2} {:>10.2}  {}",
+            m.address,
+            m.perms,
+            mb(m.rss_kb),
+            mb(m.pss_kb),
+            mb(m.swap_kb),
+            mb(m.anon_huge_kb + m.hugetlb_kb),
+            pathname
+        )?;
+    }
+
+    let eligible: Vec<&Mapping> = mappings.iter().filter(|m| m.thp_eligible).collect();
+    let anon_huge_kb: u64 = mappings.iter().map(|m| m.anon_huge_kb).sum();
+    let hugetlb_kb: u64 = mappings.iter().map(|m| m.hugetlb_kb).sum();
+    writeln!(out)?;
+    write!(
+        out,
+        "Transparent huge pages: {:.2} MB in use; {} mappings of {:.2} MB eligible",
+        mb(anon_huge_kb),
+        eligible.len(),
+        mb(eligible.iter().map(|m| m.size_kb).sum())
+    )?;
+    match thp_mode() {
+        Some(mode) => writeln!(out, " (mode: {mode})")?,
+        None => writeln!(out)?,
+    }
+    writeln!(out, "Hugetlbfs pages: {:.2} MB", mb(hugetlb_kb))
+}
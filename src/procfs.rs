@@ -0,0 +1,474 @@
+//! Helpers for reading process and system information from /proc.
+
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read};
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::model::Pressure;
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+use crate::sys;
+
+// FreeBSD has no /proc by default and Windows none at all; these are read with
+// sysctl and the Win32 API there
+#[cfg(target_os = "freebsd")]
+pub use crate::freebsd::{exe_basename, exe_path, read_cmdline, read_cmdname, read_comm, read_hostname, read_meminfo};
+#[cfg(windows)]
+pub use crate::windows::{exe_basename, exe_path, read_cmdline, read_cmdname, read_comm, read_hostname, read_meminfo};
+
+/// Where system files are read from instead of /, set when analyzing a snapshot
+static ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+/// Whether ROOT is a temporary directory, to be removed on exit
+static ROOT_IS_TEMPORARY: AtomicBool = AtomicBool::new(false);
+
+/// Run by exit() before anything else, e.g. to restore the terminal
+static EXIT_HOOK: Mutex<Option<Box<dyn FnOnce() + Send>>> = Mutex::new(None);
+
+/// Makes all system files be read below root, e.g. from an extracted snapshot.
+/// A temporary root is removed again by exit.
+pub fn set_root(root: PathBuf, temporary: bool) {
+    if ROOT.set(root).is_ok() {
+        ROOT_IS_TEMPORARY.store(temporary, Ordering::Relaxed);
+    }
+}
+
+/// Exits with the given code. std::process::exit() skips destructors, so a
+/// snapshot extracted to a temporary directory is removed here.
+pub fn exit(code: i32) -> ! {
+    run_exit_hook();
+    if ROOT_IS_TEMPORARY.load(Ordering::Relaxed)
+        && let Some(root) = ROOT.get()
+    {
+        let _ = fs::remove_dir_all(root);
+    }
+    std::process::exit(code)
+}
+
+/// Makes exit() run hook first, until run_exit_hook() is called
+#[cfg(unix)]
+pub fn set_exit_hook(hook: Box<dyn FnOnce() + Send>) {
+    *EXIT_HOOK.lock().unwrap_or_else(|e| e.into_inner()) = Some(hook);
+}
+
+/// Runs the hook set with set_exit_hook(), if it hasn't run yet
+pub fn run_exit_hook() {
+    let hook = EXIT_HOOK.lock().unwrap_or_else(|e| e.into_inner()).take();
+    if let Some(hook) = hook {
+        hook();
+    }
+}
+
+/// Holds the seconds since the Unix epoch at which a snapshot was taken
+pub const SNAPSHOT_TIMESTAMP_FILE: &str = "timestamp";
+
+/// When the snapshot being analyzed was taken; None for the live system
+pub fn snapshot_timestamp() -> Option<u64> {
+    fs::read_to_string(ROOT.get()?.join(SNAPSHOT_TIMESTAMP_FILE)).ok()?.trim().parse().ok()
+}
+
+/// The location of a system file like /proc/meminfo: the file itself, or its
+/// copy in the snapshot being analyzed
+pub fn path(path: &str) -> PathBuf {
+    match ROOT.get() {
+        Some(root) => root.join(path.trim_start_matches('/')),
+        None => PathBuf::from(path),
+    }
+}
+
+/// Reads all fields of /proc/meminfo; sizes are in kB, the HugePages_ counts in pages
+#[cfg(not(any(target_os = "freebsd", windows)))]
+pub fn read_meminfo() -> HashMap<String, u64> {
+    let mut info = HashMap::new();
+    let Ok(file) = File::open(path("/proc/meminfo")) else {
+        return info;
+    };
+    for line in BufReader::new(file).lines().map_while(Result::ok) {
+        let Some((key, rest)) = line.split_once(':') else {
+            continue;
+        };
+        if let Some(Ok(value)) = rest.split_whitespace().next().map(str::parse::<u64>) {
+            info.insert(key.to_string(), value);
+        }
+    }
+    info
+}
+
+/// One process's files, each read at most once. On Linux on x86_64 and
+/// aarch64, /proc/[pid] is opened once and the files are opened relative to
+/// it, which spares the kernel looking up the process again for every file;
+/// on hosts with many processes, these lookups take most of the scan.
+pub struct ProcessFiles {
+    pub pid: String,
+    /// None if the process has exited
+    #[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+    dir: Option<File>,
+    cmdline: OnceCell<Option<Vec<String>>>,
+    comm: OnceCell<Option<String>>,
+    exe_path: OnceCell<Option<String>>,
+    cgroup: OnceCell<Option<String>>,
+}
+
+impl ProcessFiles {
+    pub fn new(pid: &str) -> ProcessFiles {
+        ProcessFiles {
+            pid: pid.to_string(),
+            #[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+            dir: File::open(path(&format!("/proc/{pid}"))).ok(),
+            cmdline: OnceCell::new(),
+            comm: OnceCell::new(),
+            exe_path: OnceCell::new(),
+            cgroup: OnceCell::new(),
+        }
+    }
+
+    #[cfg(not(any(target_os = "freebsd", windows)))]
+    pub fn status(&self) -> Option<Status> {
+        parse_status(self.open("status")?)
+    }
+
+    /// When the process was started, in seconds since the Unix epoch
+    #[cfg(not(any(target_os = "freebsd", windows)))]
+    pub fn start_time(&self, boot_time: u64) -> Option<u64> {
+        let stat = String::from_utf8(self.read("stat")?).ok()?;
+        // The command name in parentheses may contain spaces; starttime is the 20th field after it
+        let ticks: u64 = stat.rsplit_once(')')?.1.split_whitespace().nth(19)?.parse().ok()?;
+        Some(boot_time + ticks / USER_HZ)
+    }
+
+    /// Opens one of the files in /proc/[pid], e.g. "status"
+    #[cfg(not(any(target_os = "freebsd", windows)))]
+    pub fn open(&self, name: &str) -> Option<File> {
+        #[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+        return sys::open_at(self.dir.as_ref()?, name).ok();
+        // Elsewhere, openat() isn't declared
+        #[cfg(not(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64"))))]
+        File::open(path(&format!("/proc/{}/{name}", self.pid))).ok()
+    }
+
+    #[cfg(not(any(target_os = "freebsd", windows)))]
+    fn read(&self, name: &str) -> Option<Vec<u8>> {
+        let mut data = vec![];
+        self.open(name)?.read_to_end(&mut data).ok()?;
+        Some(data)
+    }
+
+    pub fn cmdline(&self) -> Option<&[String]> {
+        #[cfg(not(any(target_os = "freebsd", windows)))]
+        let read = || self.read("cmdline").map(|data| parse_cmdline(&data));
+        #[cfg(any(target_os = "freebsd", windows))]
+        let read = || read_cmdline(&self.pid);
+        self.cmdline.get_or_init(read).as_deref()
+    }
+
+    /// The command line if it was read already, without reading it
+    pub fn cmdline_if_read(&self) -> Option<&[String]> {
+        self.cmdline.get()?.as_deref()
+    }
+
+    /// The basename of argv[0]
+    #[cfg(not(any(target_os = "freebsd", windows)))]
+    pub fn cmdname(&self) -> Option<String> {
+        let argv0 = self.cmdline()?.first()?.split(' ').next()?; // remove trailing args if embedded
+        std::path::Path::new(argv0).file_name().map(|s| s.to_string_lossy().to_string())
+    }
+
+    #[cfg(any(target_os = "freebsd", windows))]
+    pub fn cmdname(&self) -> Option<String> {
+        read_cmdname(&self.pid)
+    }
+
+    /// The name the kernel keeps for the process: the executable's basename,
+    /// truncated to 15 bytes, unless the process renamed itself
+    pub fn comm(&self) -> Option<&str> {
+        #[cfg(not(any(target_os = "freebsd", windows)))]
+        let read = || self.read("comm").map(|data| String::from_utf8_lossy(&data).trim_end_matches('\n').to_string());
+        #[cfg(any(target_os = "freebsd", windows))]
+        let read = || read_comm(&self.pid);
+        self.comm.get_or_init(read).as_deref()
+    }
+
+    /// Provides the command name when it is already known, e.g. from the status
+    pub fn set_comm(&self, comm: String) {
+        let _ = self.comm.set(Some(comm));
+    }
+
+    /// The full path of the process's executable
+    pub fn exe_path(&self) -> Option<&str> {
+        #[cfg(not(any(target_os = "freebsd", windows)))]
+        let read = || {
+            #[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+            let p = sys::read_link_at(self.dir.as_ref()?, "exe").ok()?;
+            #[cfg(not(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64"))))]
+            let p = fs::read_link(path(&format!("/proc/{}/exe", self.pid))).ok()?;
+            let p = p.to_string_lossy();
+            // The binary was replaced, e.g. by a package update
+            Some(p.strip_suffix(" (deleted)").unwrap_or(&p).to_string())
+        };
+        #[cfg(any(target_os = "freebsd", windows))]
+        let read = || exe_path(&self.pid);
+        self.exe_path.get_or_init(read).as_deref()
+    }
+
+    #[cfg(not(any(target_os = "freebsd", windows)))]
+    pub fn exe_basename(&self) -> Option<String> {
+        Some(std::path::Path::new(self.exe_path()?).file_name()?.to_string_lossy().to_string())
+    }
+
+    #[cfg(any(target_os = "freebsd", windows))]
+    pub fn exe_basename(&self) -> Option<String> {
+        exe_basename(&self.pid)
+    }
+
+    pub fn cgroup(&self) -> Option<&str> {
+        #[cfg(not(any(target_os = "freebsd", windows)))]
+        let read = || parse_cgroup(&String::from_utf8_lossy(&self.read("cgroup")?));
+        #[cfg(any(target_os = "freebsd", windows))]
+        let read = || read_cgroup(&self.pid);
+        self.cgroup.get_or_init(read).as_deref()
+    }
+}
+
+/// The subset of /proc/[pid]/status we care about; sizes in kB
+#[derive(Default)]
+pub struct Status {
+    /// The command name, as in /proc/[pid]/comm but with backslashes escaped
+    pub name: String,
+    pub ppid: u32,
+    /// The session id as seen from our pid namespace
+    pub sid: u32,
+    pub rss_kb: u64,
+    pub uid: u32,
+    /// Memory in hugetlbfs pages, which is not part of VmRSS
+    pub hugetlb_kb: u64,
+    /// mlock()ed memory, which can't be reclaimed
+    pub locked_kb: u64,
+    /// High-water mark of the resident set
+    pub hwm_kb: u64,
+    /// Peak virtual memory size
+    pub peak_kb: u64,
+    /// Anonymous memory that has been swapped out
+    pub swap_kb: u64,
+    pub threads: u64,
+}
+
+pub fn read_status(pid: &str) -> Option<Status> {
+    parse_status(File::open(path(&format!("/proc/{pid}/status"))).ok()?)
+}
+
+pub fn parse_status(file: impl Read) -> Option<Status> {
+    let mut status = Status::default();
+    for line in BufReader::new(file).lines().map_while(Result::ok) {
+        let Some((key, rest)) = line.split_once(':') else {
+            continue;
+        };
+        // Real, effective, saved, filesystem for Uid:; we report the real uid
+        let value = rest.split_whitespace().next().unwrap_or("");
+        match key {
+            "Name" => status.name = rest.trim_start_matches('\t').to_string(),
+            "PPid" => status.ppid = value.parse().ok()?,
+            "NSsid" => status.sid = value.parse().ok()?,
+            "VmRSS" => status.rss_kb = value.parse().ok()?,
+            "Uid" => status.uid = value.parse().ok()?,
+            "HugetlbPages" => status.hugetlb_kb = value.parse().ok()?,
+            "VmLck" => status.locked_kb = value.parse().ok()?,
+            "VmHWM" => status.hwm_kb = value.parse().ok()?,
+            "VmPeak" => status.peak_kb = value.parse().ok()?,
+            "VmSwap" => status.swap_kb = value.parse().ok()?,
+            "Threads" => status.threads = value.parse().ok()?,
+            _ => {}
+        }
+    }
+    Some(status)
+}
+
+/// Maps uids to login names using /etc/passwd
+pub fn read_user_names() -> HashMap<u32, String> {
+    let mut users = HashMap::new();
+    let Ok(data) = fs::read_to_string(path("/etc/passwd")) else {
+        return users;
+    };
+    for line in data.lines() {
+        let mut fields = line.split(':');
+        let (Some(name), Some(_), Some(uid)) = (fields.next(), fields.next(), fields.next()) else {
+            continue;
+        };
+        if let Ok(uid) = uid.parse::<u32>() {
+            users.entry(uid).or_insert_with(|| name.to_string());
+        }
+    }
+    users
+}
+
+#[cfg(not(any(target_os = "freebsd", windows)))]
+pub fn read_cmdline(pid: &str) -> Option<Vec<String>> {
+    Some(parse_cmdline(&fs::read(path(&format!("/proc/{pid}/cmdline"))).ok()?))
+}
+
+fn parse_cmdline(data: &[u8]) -> Vec<String> {
+    data
+        .split(|b| *b == 0u8)
+        .filter(|s| !s.is_empty())
+        .map(|s| String::from_utf8_lossy(s).to_string())
+        .collect()
+}
+
+/// The full path of the process's executable
+#[cfg(not(any(target_os = "freebsd", windows)))]
+pub fn exe_path(pid: &str) -> Option<String> {
+    let p = fs::read_link(path(&format!("/proc/{pid}/exe"))).ok()?;
+    let p = p.to_string_lossy();
+    // The binary was replaced, e.g. by a package update
+    Some(p.strip_suffix(" (deleted)").unwrap_or(&p).to_string())
+}
+
+/// The name the kernel keeps for a process: the executable's basename,
+/// truncated to 15 bytes, unless the process renamed itself
+#[cfg(not(any(target_os = "freebsd", windows)))]
+pub fn read_comm(pid: &str) -> Option<String> {
+    let comm = fs::read_to_string(path(&format!("/proc/{pid}/comm"))).ok()?;
+    Some(comm.trim_end_matches('\n').to_string())
+}
+
+/// The environment variables of a process. The environment is only readable
+/// for our own processes, unless we are root.
+pub fn read_environ(pid: &str) -> Option<Vec<(String, String)>> {
+    let data = fs::read(path(&format!("/proc/{pid}/environ"))).ok()?;
+    let vars = data.split(|b| *b == 0).filter_map(|var| {
+        let var = String::from_utf8_lossy(var);
+        let (name, value) = var.split_once('=')?;
+        Some((name.to_string(), value.to_string()))
+    });
+    Some(vars.collect())
+}
+
+pub fn read_environ_var(pid: &str, name: &str) -> Option<String> {
+    read_environ(pid)?.into_iter().find(|(n, _)| n == name).map(|(_, value)| value)
+}
+
+/// The cgroup a process is in: its cgroup v2 path, or on systems that only
+/// use cgroup v1 for it, the path in systemd's hierarchy
+pub fn read_cgroup(pid: &str) -> Option<String> {
+    parse_cgroup(&fs::read_to_string(path(&format!("/proc/{pid}/cgroup"))).ok()?)
+}
+
+fn parse_cgroup(content: &str) -> Option<String> {
+    let unified = content.lines().find_map(|l| l.strip_prefix("0::")).filter(|p| *p != "/");
+    let systemd = || content.lines().find_map(|l| l.split_once(":name=systemd:").map(|(_, p)| p));
+    unified.or_else(systemd).map(str::to_string)
+}
+
+/// The unit of the times in /proc/[pid]/stat, which the kernel fixes at 100
+/// regardless of its internal tick rate
+const USER_HZ: u64 = 100;
+
+/// When the system booted, in seconds since the Unix epoch
+pub fn read_boot_time() -> Option<u64> {
+    let data = fs::read_to_string(path("/proc/stat")).ok()?;
+    data.lines().find_map(|l| l.strip_prefix("btime "))?.trim().parse().ok()
+}
+
+pub fn is_numeric_dir(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_digit())
+}
+
+#[cfg(not(any(target_os = "freebsd", windows)))]
+pub fn read_hostname() -> String {
+    fs::read_to_string(path("/proc/sys/kernel/hostname"))
+        .map(|s| s.trim().to_string())
+        .unwrap_or_default()
+}
+
+/// The kernel's base page size, as seen in our own memory mappings
+pub fn page_size_kb() -> u64 {
+    let Ok(file) = File::open(path("/proc/self/smaps")) else {
+        return 4;
+    };
+    for line in BufReader::new(file).lines().map_while(Result::ok) {
+        if let Some(rest) = line.strip_prefix("KernelPageSize:") {
+            return rest.split_whitespace().next().and_then(|v| v.parse().ok()).unwrap_or(4);
+        }
+    }
+    4
+}
+
+pub fn read_memory_pressure() -> Option<Pressure> {
+    let data = fs::read_to_string(path("/proc/pressure/memory")).ok()?;
+    let mut pressure = Pressure::default();
+    for line in data.lines() {
+        let mut fields = line.split_whitespace();
+        let avgs = match fields.next() {
+            Some("some") => &mut pressure.some,
+            Some("full") => &mut pressure.full,
+            _ => continue,
+        };
+        for field in fields {
+            let (i, value) = match field.split_once('=') {
+                Some(("avg10", v)) => (0, v),
+                Some(("avg60", v)) => (1, v),
+                Some(("avg300", v)) => (2, v),
+                _ => continue,
+            };
+            avgs[i] = value.parse().ok()?;
+        }
+    }
+    Some(pressure)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATUS: &str = "Name:\tWeb Content\nUmask:\t0022\nState:\tS (sleeping)\nTgid:\t4242\nNgid:\t0\nPid:\t4242\nPPid:\t1000\n\
+        TracerPid:\t0\nUid:\t1001\t1002\t1003\t1004\nGid:\t100\t100\t100\t100\nNSpid:\t4242\t7\nNSsid:\t999\t1\n\
+        VmPeak:\t 3000000 kB\nVmSize:\t 2900000 kB\nVmLck:\t      16 kB\nVmHWM:\t  500000 kB\nVmRSS:\t  400000 kB\n\
+        RssAnon:\t  300000 kB\nHugetlbPages:\t    2048 kB\nVmSwap:\t    1234 kB\nThreads:\t37\n";
+
+    #[test]
+    fn parses_status() {
+        let status = parse_status(STATUS.as_bytes()).unwrap();
+        assert_eq!(status.name, "Web Content");
+        assert_eq!((status.ppid, status.sid, status.uid), (1000, 999, 1001));
+        assert_eq!(status.rss_kb, 400_000);
+        assert_eq!(status.hugetlb_kb, 2048);
+        assert_eq!(status.locked_kb, 16);
+        assert_eq!(status.hwm_kb, 500_000);
+        assert_eq!(status.peak_kb, 3_000_000);
+        assert_eq!(status.swap_kb, 1234);
+        assert_eq!(status.threads, 37);
+    }
+
+    #[test]
+    fn keeps_names_as_written() {
+        // The kernel escapes backslashes and newlines in names
+        let status = parse_status("Name:\t\\tab: \\n \n".as_bytes()).unwrap();
+        assert_eq!(status.name, "\\tab: \\n ");
+    }
+
+    #[test]
+    fn missing_fields_are_zero() {
+        // Kernel threads have no Vm* lines
+        let status = parse_status("Name:\tkthreadd\nPPid:\t0\nThreads:\t1\n".as_bytes()).unwrap();
+        assert_eq!(status.name, "kthreadd");
+        assert_eq!((status.rss_kb, status.swap_kb, status.peak_kb, status.threads), (0, 0, 0, 1));
+    }
+
+    #[test]
+    fn rejects_malformed_numbers() {
+        assert!(parse_status("Name:\ta\nVmRSS:\tmany kB\n".as_bytes()).is_none());
+        assert!(parse_status("PPid:\n".as_bytes()).is_none());
+    }
+
+    #[test]
+    fn parses_cgroup() {
+        assert_eq!(parse_cgroup("0::/user.slice/user-1000.slice/session-2.scope\n").as_deref(), Some("/user.slice/user-1000.slice/session-2.scope"));
+        // The root of the unified hierarchy says nothing; hybrid setups name systemd's
+        let hybrid = "12:memory:/user.slice\n1:name=systemd:/user.slice/app.service\n0::/\n";
+        assert_eq!(parse_cgroup(hybrid).as_deref(), Some("/user.slice/app.service"));
+        assert_eq!(parse_cgroup("0::/\n"), None);
+        assert_eq!(parse_cgroup(""), None);
+    }
+}
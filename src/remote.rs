@@ -0,0 +1,46 @@
+//! Collection on other machines over SSH. memory needs to be installed there;
+//! it is run with `--format=json` and its report is read back in.
+
+use std::process::Command;
+use std::thread;
+
+use crate::json;
+use crate::model::Report;
+
+/// Quotes arg for the remote shell, which ssh hands its command line to
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+fn collect_host(host: &str, command: &str, args: &[String]) -> Result<Report, String> {
+    let output = Command::new("ssh")
+        // Fail instead of prompting for passwords, which would interleave for several hosts
+        // `--` keeps a host starting with '-' from being read as an option
+        .args(["-o", "BatchMode=yes", "--", host, command])
+        .args(args.iter().map(|arg| shell_quote(arg)))
+        .output()
+        .map_err(|e| format!("failed to run ssh: {e}"))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(stderr.lines().last().unwrap_or("ssh failed").to_string());
+    }
+    let value = json::parse(&String::from_utf8_lossy(&output.stdout))?;
+    Report::from_json(&value)
+}
+
+/// Collects reports from all hosts in parallel. Hosts that fail are reported
+/// on stderr and left out.
+pub fn collect_reports(hosts: &[String], command: &str, args: &[String]) -> Vec<Report> {
+    let results: Vec<_> = thread::scope(|s| {
+        let handles: Vec<_> = hosts.iter().map(|host| s.spawn(|| collect_host(host, command, args))).collect();
+        handles.into_iter().map(|h| h.join().unwrap_or_else(|_| Err("collection panicked".to_string()))).collect()
+    });
+    let mut reports = vec![];
+    for (host, result) in hosts.iter().zip(results) {
+        match result {
+            Ok(report) => reports.push(report),
+            Err(e) => eprintln!("{host}: {e}"),
+        }
+    }
+    reports
+}
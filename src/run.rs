@@ -0,0 +1,310 @@
+//! The `run` subcommand: runs a command and samples the memory of its whole
+//! process tree while it executes, like `/usr/bin/time -v` but counting all
+//! descendants instead of only the largest child. With `--cgroup`, the command
+//! runs in a cgroup of its own and the kernel's accounting is reported instead.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus};
+use std::time::{Duration, Instant};
+
+use crate::cgroup::{self, TransientCgroup};
+use crate::json::{self, Value};
+use crate::model::SCHEMA_VERSION;
+use crate::procfs::{is_numeric_dir, read_status};
+use crate::smaps::read_rollup;
+use crate::sys::{self, SIGINT, SIGQUIT};
+
+/// The sizes from memory.stat that are reported
+const STAT_KEYS: [&str; 5] = ["anon", "file", "kernel", "shmem", "sock"];
+
+/// Memory of the process tree at one point in time; sizes in kB
+#[derive(Default)]
+struct Sample {
+    processes: usize,
+    rss_kb: u64,
+    pss_kb: u64,
+}
+
+/// Peak and running total of a series of values
+#[derive(Default)]
+struct Stat {
+    peak: u64,
+    sum: u64,
+}
+
+impl Stat {
+    fn add(&mut self, value: u64) {
+        self.peak = self.peak.max(value);
+        self.sum += value;
+    }
+}
+
+/// Parent pid of every process
+fn parents() -> HashMap<u32, u32> {
+    let Ok(proc) = fs::read_dir("/proc") else {
+        return HashMap::new();
+    };
+    let mut parents = HashMap::new();
+    for entry in proc.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !is_numeric_dir(&name) {
+            continue;
+        }
+        if let (Ok(pid), Some(status)) = (name.parse(), read_status(&name)) {
+            parents.insert(pid, status.ppid);
+        }
+    }
+    parents
+}
+
+/// Updates the set of processes in the tree and measures them. Processes stay
+/// members once seen, so that daemons reparented to init are still counted.
+fn sample(members: &mut HashSet<u32>) -> Sample {
+    let parents = parents();
+    members.retain(|pid| parents.contains_key(pid));
+    // Children can be started between two samples, so add them until nothing changes
+    loop {
+        let new: Vec<u32> = parents
+            .iter()
+            .filter(|(pid, ppid)| members.contains(ppid) && !members.contains(pid))
+            .map(|(pid, _)| *pid)
+            .collect();
+        if new.is_empty() {
+            break;
+        }
+        members.extend(new);
+    }
+
+    let mut sample = Sample::default();
+    for pid in members.iter() {
+        let pid = pid.to_string();
+        let Some(status) = read_status(&pid) else {
+            continue;
+        };
+        sample.processes += 1;
+        sample.rss_kb += status.rss_kb;
+        sample.pss_kb += read_rollup(&pid).map_or(status.rss_kb, |r| r.pss_kb);
+    }
+    sample
+}
+
+fn spawn(command: &mut Command, name: &str) -> Child {
+    let child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            eprintln!("Failed to run {name}: {e}");
+            std::process::exit(127);
+        }
+    };
+    // Ctrl-C reaches the whole process group; let the command decide what to do
+    // with it and report whatever it used until then
+    sys::ignore_signal(SIGINT);
+    sys::ignore_signal(SIGQUIT);
+    child
+}
+
+/// The exit code a shell would report for the command
+fn exit_code(status: ExitStatus) -> i32 {
+    status.code().unwrap_or_else(|| 128 + status.signal().unwrap_or(0))
+}
+
+/// Prints how the command ended and returns the exit code to exit with
+fn print_status(out: &mut dyn Write, argv: &[String], status: ExitStatus, elapsed: Duration) -> io::Result<i32> {
+    let code = exit_code(status);
+    writeln!(out)?;
+    writeln!(out, "Command:          {}", argv.join(" "))?;
+    match status.signal() {
+        Some(signal) => writeln!(out, "Killed by signal: {signal}")?,
+        None => writeln!(out, "Exit status:      {code}")?,
+    }
+    writeln!(out, "Elapsed:          {:.2} s", elapsed.as_secs_f64())?;
+    Ok(code)
+}
+
+/// How the command ended, followed by the given measurements, as JSON
+fn write_json(
+    out: &mut dyn Write,
+    argv: &[String],
+    status: ExitStatus,
+    elapsed: Duration,
+    measurements: Vec<(&str, Value)>,
+) -> io::Result<i32> {
+    let mut fields = vec![
+        ("schema_version", SCHEMA_VERSION.into()),
+        ("command", Value::Array(argv.iter().map(|a| a.as_str().into()).collect())),
+        ("exit_code", (exit_code(status) as f64).into()),
+        ("signal", status.signal().map(|s| s as f64).into()),
+        ("elapsed_s", elapsed.as_secs_f64().into()),
+    ];
+    fields.extend(measurements);
+    let value = Value::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect());
+    out.write_all(value.to_pretty_string().as_bytes())?;
+    Ok(exit_code(status))
+}
+
+/// Runs argv to completion, sampling its process tree at the given interval,
+/// and prints a summary, as JSON if requested. Returns the exit code to exit with.
+pub fn run_command(out: &mut dyn Write, argv: &[String], interval: Duration, json: bool) -> io::Result<i32> {
+    let start = Instant::now();
+    let mut child = spawn(Command::new(&argv[0]).args(&argv[1..]), &argv[0]);
+
+    let mut members = HashSet::from([child.id()]);
+    let mut seen = HashSet::new();
+    let (mut samples, mut processes, mut rss, mut pss) = (0, 0, Stat::default(), Stat::default());
+    let status = loop {
+        let s = sample(&mut members);
+        seen.extend(members.iter().copied());
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        samples += 1;
+        processes = processes.max(s.processes);
+        rss.add(s.rss_kb);
+        pss.add(s.pss_kb);
+        std::thread::sleep(interval);
+    };
+
+    if json {
+        let stat_json = |s: &Stat| {
+            let average = (samples > 0).then(|| s.sum as f64 / samples as f64);
+            json::object([("peak", s.peak.into()), ("average", average.into())])
+        };
+        let measurements = vec![
+            ("samples", (samples as u64).into()),
+            ("interval_ms", (interval.as_millis() as u64).into()),
+            ("processes", (seen.len() as u64).into()),
+            ("max_processes", (processes as u64).into()),
+            ("rss_kb", stat_json(&rss)),
+            ("pss_kb", stat_json(&pss)),
+        ];
+        return write_json(out, argv, status, start.elapsed(), measurements);
+    }
+    let code = print_status(out, argv, status, start.elapsed())?;
+    writeln!(out, "Samples:          {samples} every {} ms", interval.as_millis())?;
+    writeln!(out, "Processes:        {} (at most {processes} at once)", seen.len())?;
+    if samples == 0 {
+        writeln!(out, "The command exited before its memory could be sampled")?;
+        return Ok(code);
+    }
+    writeln!(out)?;
+    writeln!(out, "{:<10} {:>12} {:>12}", "", "Peak(MB)", "Average(MB)")?;
+    for (name, stat) in [("RSS", rss), ("PSS", pss)] {
+        let average = stat.sum as f64 / samples as f64;
+        writeln!(out, "{:<10} {:>12.2} {:>12.2}", name, stat.peak as f64 / 1024.0, average / 1024.0)?;
+    }
+    Ok(code)
+}
+
+/// Finds program in the directories of $PATH, like the shell
+fn find_in_path(program: &str) -> Option<PathBuf> {
+    std::env::split_paths(&std::env::var_os("PATH")?).map(|dir| dir.join(program)).find(|path| path.is_file())
+}
+
+/// Runs memory itself again in a systemd scope with cgroup delegation, where
+/// it is allowed to create the cgroup for the command
+fn reexec_in_scope(systemd_run: &Path) -> io::Result<i32> {
+    let mut command = Command::new(systemd_run);
+    if read_status("self").is_some_and(|s| s.uid != 0) {
+        command.arg("--user");
+    }
+    command
+        .args(["--scope", "--quiet", "--property=Delegate=yes", "--"])
+        .arg(std::env::current_exe()?)
+        .args(std::env::args_os().skip(1))
+        .env(cgroup::SCOPE_ENV, "1");
+    let status = spawn(&mut command, "systemd-run").wait()?;
+    Ok(status.code().unwrap_or_else(|| 128 + status.signal().unwrap_or(0)))
+}
+
+/// Like run_command, but runs argv in a cgroup of its own and reports the
+/// kernel's accounting for it. This includes processes too short-lived to be
+/// sampled, as well as page cache and kernel memory charged to the command.
+pub fn run_in_cgroup(out: &mut dyn Write, argv: &[String], interval: Duration, json: bool) -> io::Result<i32> {
+    let cgroup = match TransientCgroup::create() {
+        Ok(cgroup) => cgroup,
+        Err(e) => {
+            if std::env::var_os(cgroup::SCOPE_ENV).is_none()
+                && let Some(systemd_run) = find_in_path("systemd-run")
+            {
+                return reexec_in_scope(&systemd_run);
+            }
+            eprintln!("Failed to create a cgroup for the command: {e}");
+            std::process::exit(1);
+        }
+    };
+    let mut procs = cgroup.procs()?;
+
+    let start = Instant::now();
+    let mut command = Command::new(&argv[0]);
+    command.args(&argv[1..]);
+    // SAFETY: the closure only issues a write() on an already open file, which
+    // is async-signal-safe, and allocates nothing
+    unsafe {
+        command.pre_exec(move || procs.write_all(b"0"));
+    }
+    let mut child = spawn(&mut command, &argv[0]);
+
+    let (mut samples, mut current) = (0, Stat::default());
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if let Some(bytes) = cgroup.current() {
+            samples += 1;
+            current.add(bytes);
+        }
+        std::thread::sleep(interval);
+    };
+
+    let events = cgroup::read_keyed(&cgroup.dir, "memory.events");
+    let oom_kills = events.get("oom_kill").copied().unwrap_or(0);
+    // memory.peak also covers the time between samples
+    let peak = cgroup.peak().unwrap_or(current.peak);
+    let average = current.sum.checked_div(samples);
+    let at_exit = cgroup.current().unwrap_or(0);
+    // What is still charged once the command is gone, mostly page cache
+    let stat = cgroup::read_keyed(&cgroup.dir, "memory.stat");
+
+    if json {
+        let kb = |bytes: u64| bytes / 1024;
+        let stat_kb = STAT_KEYS.iter().filter_map(|k| Some((k.to_string(), kb(*stat.get(*k)?).into()))).collect();
+        let measurements = vec![
+            ("cgroup", cgroup.dir.to_string_lossy().into_owned().into()),
+            ("oom_kills", oom_kills.into()),
+            ("memory_kb", json::object([("peak", kb(peak).into()), ("average", average.map(kb).into()), ("at_exit", kb(at_exit).into())])),
+            ("stat_at_exit_kb", Value::Object(stat_kb)),
+        ];
+        return write_json(out, argv, status, start.elapsed(), measurements);
+    }
+
+    let code = print_status(out, argv, status, start.elapsed())?;
+    writeln!(out, "Cgroup:           {}", cgroup.dir.display())?;
+    if oom_kills > 0 {
+        writeln!(out, "OOM kills:        {oom_kills}")?;
+    }
+    writeln!(out)?;
+    let mb = |bytes: u64| bytes as f64 / 1024.0 / 1024.0;
+    writeln!(out, "{:<16} {:>12.2}", "Peak(MB)", mb(peak))?;
+    if let Some(average) = average {
+        writeln!(out, "{:<16} {:>12.2}", "Average(MB)", mb(average))?;
+    }
+    writeln!(out, "{:<16} {:>12.2}", "At exit(MB)", mb(at_exit))?;
+
+    writeln!(out)?;
+    writeln!(out, "memory.stat at exit:")?;
+    for key in STAT_KEYS {
+        if let Some(value) = stat.get(key) {
+            writeln!(out, "  {:<14} {:>12.2} MB", key, mb(*value))?;
+        }
+    }
+    for key in ["pgfault", "pgmajfault"] {
+        if let Some(value) = stat.get(key) {
+            writeln!(out, "  {key:<14} {value:>12}")?;
+        }
+    }
+    Ok(code)
+}
@@ -0,0 +1,193 @@
+//! Recognizes applications confined by Flatpak or Snap, and containers. Their
+//! processes run under sandbox helper names like bwrap, or are a whole system
+//! of their own, but the cgroup they are in is named after them.
+
+use std::collections::HashMap;
+use std::fs;
+#[cfg(not(any(target_os = "freebsd", windows)))]
+use std::io::Read;
+use std::sync::Mutex;
+
+use crate::json;
+use crate::procfs::{self, ProcessFiles};
+
+/// "machine: <name>" for processes of a container, "flatpak: <app id>" or
+/// "snap: <name>" for processes of a sandboxed app
+pub fn sandbox_name(files: &ProcessFiles) -> Option<String> {
+    if let Some(name) = files.cgroup().and_then(|c| machine_from_cgroup(c).or_else(|| from_cgroup(c))) {
+        return Some(name);
+    }
+    #[cfg(not(any(target_os = "freebsd", windows)))]
+    if let Some(id) = flatpak_id(files) {
+        return Some(format!("flatpak: {id}"));
+    }
+    let exe = files.exe_path()?;
+    let snap = exe.strip_prefix("/snap/")?.split('/').next()?;
+    Some(format!("snap: {snap}"))
+}
+
+/// Parses the cgroups of systemd-nspawn containers and other machines
+/// registered with systemd-machined (machine.slice/systemd-nspawn@web.service,
+/// machine.slice/machine-web.scope), and of LXC and LXD containers
+/// (lxc.payload.web, or lxc/web with older LXC versions)
+fn machine_from_cgroup(path: &str) -> Option<String> {
+    let mut dirs = path.split('/');
+    while let Some(dir) = dirs.next() {
+        let dir = dir.replace("\\x2d", "-");
+        let name = if let Some(rest) = dir.strip_prefix("systemd-nspawn@") {
+            rest.strip_suffix(".service")
+        } else if let Some(rest) = dir.strip_prefix("machine-") {
+            rest.strip_suffix(".scope")
+        } else if dir == "lxc" {
+            dirs.next()
+        } else {
+            // The monitor process runs next to the container's payload
+            dir.strip_prefix("lxc.payload.").or_else(|| dir.strip_prefix("lxc.monitor."))
+        };
+        if let Some(name) = name.filter(|n| !n.is_empty()) {
+            return Some(format!("machine: {name}"));
+        }
+    }
+    None
+}
+
+/// Parses unit names like app-flatpak-org.mozilla.firefox-2537.scope and
+/// snap.spotify.spotify-3f1c….scope
+fn from_cgroup(path: &str) -> Option<String> {
+    path.rsplit('/').find_map(|unit| {
+        let unit = unit.replace("\\x2d", "-");
+        let unit = unit.strip_suffix(".scope").or_else(|| unit.strip_suffix(".service"))?;
+        if let Some(rest) = unit.strip_prefix("app-flatpak-") {
+            // The instance number
+            let id = rest.rsplit_once('-').filter(|(_, n)| n.chars().all(|c| c.is_ascii_digit())).map_or(rest, |(id, _)| id);
+            Some(format!("flatpak: {id}"))
+        } else {
+            let name = unit.strip_prefix("snap.")?.split('.').next()?;
+            Some(format!("snap: {name}"))
+        }
+    })
+}
+
+/// The app ID from the .flatpak-info file at the root of the sandbox, which
+/// is only readable for our own processes. It is only looked for if the
+/// process is bwrap or flatpak, or runs in a cgroup of theirs.
+#[cfg(not(any(target_os = "freebsd", windows)))]
+fn flatpak_id(files: &ProcessFiles) -> Option<String> {
+    let comm = files.comm().unwrap_or_default();
+    if comm != "bwrap" && !comm.starts_with("flatpak") && !files.cgroup().is_some_and(|c| c.contains("flatpak")) {
+        return None;
+    }
+    let mut info = String::new();
+    files.open("root/.flatpak-info")?.read_to_string(&mut info).ok()?;
+    let mut in_application = false;
+    for line in info.lines() {
+        if line.starts_with('[') {
+            in_application = line == "[Application]";
+        } else if in_application && let Some(id) = line.strip_prefix("name=") {
+            return Some(id.trim().to_string());
+        }
+    }
+    None
+}
+
+/// How container runtimes name the cgroup of a container, followed by its ID
+const CONTAINER_PREFIXES: [&str; 4] = ["docker-", "libpod-", "cri-containerd-", "crio-"];
+
+/// Container names by ID, as looked up so far
+static CONTAINER_NAMES: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+
+/// The name of the Docker, Podman, containerd or CRI-O container a process
+/// runs in. Unlike the machines above, such containers usually run a single
+/// service, whose processes are still named as usual.
+pub fn container_name(files: &ProcessFiles) -> Option<String> {
+    let id = container_id(files.cgroup()?)?;
+    let mut names = CONTAINER_NAMES.lock().unwrap_or_else(|e| e.into_inner());
+    let name = names.get_or_insert_default().entry(id.to_string()).or_insert_with(|| {
+        docker_name(id).or_else(|| podman_name(id)).unwrap_or_else(|| id[..12].to_string())
+    });
+    Some(name.clone())
+}
+
+/// Finds the container ID in cgroups like system.slice/docker-<id>.scope,
+/// docker/<id> or kubepods.slice/…/cri-containerd-<id>.scope. The scope of
+/// Podman's conmon, libpod-conmon-<id>.scope, is outside the container.
+fn container_id(path: &str) -> Option<&str> {
+    path.rsplit('/').find_map(|dir| {
+        let dir = dir.strip_suffix(".scope").unwrap_or(dir);
+        let id = CONTAINER_PREFIXES.iter().find_map(|p| dir.strip_prefix(p)).unwrap_or(dir);
+        (id.len() == 64 && id.bytes().all(|b| b.is_ascii_hexdigit())).then_some(id)
+    })
+}
+
+/// The name from Docker's configuration of the container, readable by root
+fn docker_name(id: &str) -> Option<String> {
+    let config = fs::read_to_string(procfs::path(&format!("/var/lib/docker/containers/{id}/config.v2.json"))).ok()?;
+    let name = json::parse(&config).ok()?.get("Name")?.as_str()?.trim_start_matches('/').to_string();
+    Some(name)
+}
+
+/// The name from the containers of root's Podman storage
+fn podman_name(id: &str) -> Option<String> {
+    let containers = fs::read_to_string(procfs::path("/var/lib/containers/storage/overlay-containers/containers.json")).ok()?;
+    let containers = json::parse(&containers).ok()?;
+    let container = containers.as_array()?.iter().find(|c| c.get("id").and_then(json::Value::as_str) == Some(id))?;
+    Some(container.get("names")?.as_array()?.first()?.as_str()?.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "4f3c2b1a0e9d8c7b6a5f4e3d2c1b0a9f8e7d6c5b4a3f2e1d0c9b8a7f6e5d4c3b";
+
+    #[test]
+    fn finds_container_ids() {
+        for path in [
+            format!("/system.slice/docker-{ID}.scope"),
+            format!("/docker/{ID}"),
+            format!("/machine.slice/libpod-{ID}.scope/container"),
+            format!("/kubepods.slice/kubepods-besteffort.slice/kubepods-besteffort-pod1.slice/cri-containerd-{ID}.scope"),
+            format!("/kubepods/burstable/pod1/crio-{ID}.scope"),
+        ] {
+            assert_eq!(container_id(&path), Some(ID), "{path}");
+        }
+    }
+
+    #[test]
+    fn ignores_what_is_not_a_container() {
+        // Podman's monitor runs outside of the container
+        assert_eq!(container_id(&format!("/machine.slice/libpod-conmon-{ID}.scope")), None);
+        assert_eq!(container_id(&format!("/system.slice/docker-{}.scope", &ID[..63])), None);
+        assert_eq!(container_id(&format!("/system.slice/docker-{}x.scope", &ID[..63])), None);
+        assert_eq!(container_id("/system.slice/docker.service"), None);
+        assert_eq!(container_id("/user.slice/user-1000.slice/session-2.scope"), None);
+        assert_eq!(container_id(""), None);
+    }
+
+    #[test]
+    fn finds_machines() {
+        let machine = |path: &str| machine_from_cgroup(path);
+        assert_eq!(machine("/machine.slice/systemd-nspawn@web.service/payload").as_deref(), Some("machine: web"));
+        assert_eq!(machine("/machine.slice/machine-web.scope/payload/system.slice").as_deref(), Some("machine: web"));
+        // systemd escapes dashes in unit names
+        assert_eq!(machine("/machine.slice/machine-my\\x2dvm.scope").as_deref(), Some("machine: my-vm"));
+        assert_eq!(machine("/lxc.payload.db/system.slice/cron.service").as_deref(), Some("machine: db"));
+        assert_eq!(machine("/lxc.monitor.db").as_deref(), Some("machine: db"));
+        assert_eq!(machine("/lxc/db/init.scope").as_deref(), Some("machine: db"));
+        assert_eq!(machine("/machine.slice"), None);
+        assert_eq!(machine("/machine.slice/machine-.scope"), None);
+        assert_eq!(machine("/lxc"), None);
+        assert_eq!(machine("/user.slice/user-1000.slice/user@1000.service/app.slice"), None);
+    }
+
+    #[test]
+    fn finds_apps_in_cgroups() {
+        let app = |path: &str| from_cgroup(path);
+        let user = "/user.slice/user-1000.slice/user@1000.service/app.slice";
+        assert_eq!(app(&format!("{user}/app-flatpak-org.mozilla.firefox-2537.scope")).as_deref(), Some("flatpak: org.mozilla.firefox"));
+        assert_eq!(app(&format!("{user}/app-flatpak-org.gnome.Maps.scope")).as_deref(), Some("flatpak: org.gnome.Maps"));
+        assert_eq!(app(&format!("{user}/snap.spotify.spotify-3f1c.scope")).as_deref(), Some("snap: spotify"));
+        assert_eq!(app("/system.slice/snap.lxd.daemon.service").as_deref(), Some("snap: lxd"));
+        assert_eq!(app(&format!("{user}/app-gnome-org.gnome.Terminal-1234.scope")), None);
+    }
+}
@@ -0,0 +1,61 @@
+//! Identifies the login session or terminal a process belongs to, so that
+//! memory can be attributed to logins rather than programs.
+
+use std::fs;
+
+use crate::procfs;
+
+/// Where logind records its sessions, one file per session
+pub const SESSIONS_DIR: &str = "/run/systemd/sessions";
+
+/// Describes the logind session of a process, e.g. "session 3 (alice on tty2)",
+/// or else its controlling terminal
+pub fn session_name(pid: u32) -> String {
+    let pid = pid.to_string();
+    if let Some(id) = procfs::read_cgroup(&pid).as_deref().and_then(logind_session) {
+        return describe_session(id);
+    }
+    read_tty(&pid).map_or_else(|| "no session".to_string(), |tty| format!("terminal {tty}"))
+}
+
+/// The session id from a cgroup like /user.slice/user-1000.slice/session-3.scope
+fn logind_session(cgroup: &str) -> Option<&str> {
+    cgroup.split('/').find_map(|dir| dir.strip_prefix("session-")?.strip_suffix(".scope"))
+}
+
+fn describe_session(id: &str) -> String {
+    let info = fs::read_to_string(procfs::path(&format!("{SESSIONS_DIR}/{id}"))).unwrap_or_default();
+    let field = |key: &str| {
+        info.lines().find_map(|l| l.strip_prefix(key)?.strip_prefix('=')).filter(|v| !v.is_empty())
+    };
+    let mut details = vec![];
+    if let Some(user) = field("USER") {
+        details.push(user.to_string());
+    }
+    if let Some(tty) = field("TTY") {
+        details.push(format!("on {tty}"));
+    } else if let Some(host) = field("REMOTE_HOST") {
+        details.push(format!("from {host}"));
+    } else if let Some(seat) = field("SEAT") {
+        details.push(format!("on {seat}"));
+    }
+    if details.is_empty() { format!("session {id}") } else { format!("session {id} ({})", details.join(" ")) }
+}
+
+/// The controlling terminal from /proc/[pid]/stat, e.g. "pts/3"
+fn read_tty(pid: &str) -> Option<String> {
+    let stat = fs::read_to_string(procfs::path(&format!("/proc/{pid}/stat"))).ok()?;
+    // The command name in parentheses may contain spaces; tty_nr is the 5th field after it
+    let tty_nr: u32 = stat.rsplit_once(')')?.1.split_whitespace().nth(4)?.parse().ok()?;
+    if tty_nr == 0 {
+        return None;
+    }
+    let major = (tty_nr >> 8) & 0xfff;
+    let minor = (tty_nr & 0xff) | ((tty_nr >> 12) & 0xfff00);
+    Some(match major {
+        136..=143 => format!("pts/{}", (major - 136) * 256 + minor),
+        4 if minor < 64 => format!("tty{minor}"),
+        4 => format!("ttyS{}", minor - 64),
+        _ => format!("{major}:{minor}"),
+    })
+}
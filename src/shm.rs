@@ -0,0 +1,250 @@
+//! The `shm` subcommand: SysV and POSIX shared memory segments and the
+//! applications they are attached to.
+//!
+//! Shared memory isn't owned by any single process, so it is easily missed
+//! when looking at processes. SysV segments are listed in /proc/sysvipc/shm;
+//! POSIX segments are files in /dev/shm. Attached processes are found by
+//! looking for the segments in /proc/[pid]/maps.
+//!
+//! memfds and shared anonymous mappings have no name to look them up by; they
+//! are found in the maps and open files of the processes themselves, and told
+//! apart by their inode. Wayland compositors, browsers and PipeWire pass a lot
+//! of memory around in memfds.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::MetadataExt;
+
+use crate::model::Report;
+use crate::procfs::{self, is_numeric_dir};
+
+struct Segment {
+    name: String,
+    size_kb: u64,
+    /// Resident size; None if the kernel doesn't report it
+    rss_kb: Option<u64>,
+    pids: BTreeSet<u32>,
+}
+
+/// Processes attached to each SysV segment (by shmid) and POSIX segment (by
+/// path), and the memfds and shared anonymous memory found (by device and inode)
+#[derive(Default)]
+struct Attachments {
+    sysv: HashMap<u64, BTreeSet<u32>>,
+    posix: HashMap<String, BTreeSet<u32>>,
+    anonymous: HashMap<(String, u64), Segment>,
+}
+
+/// The name of a memfd or shared anonymous mapping, from its path in maps or
+/// an fd link and its inode
+fn anonymous_name(path: &str, inode: u64) -> Option<String> {
+    if let Some(name) = path.strip_prefix("/memfd:") {
+        return Some(format!("memfd: {}", name.trim_end_matches(" (deleted)")));
+    }
+    // Shared anonymous memory is backed by an unlinked /dev/zero in shmem
+    (path == "/dev/zero (deleted)" || path.starts_with("[anon_shmem")).then(|| format!("anon: inode {inode}"))
+}
+
+/// Device numbers as maps prints them, e.g. "00:01"
+fn device(dev: u64) -> String {
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+    let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+    format!("{major:02x}:{minor:02x}")
+}
+
+impl Attachments {
+    /// Records that pid holds a memfd or shared anonymous memory. Sizes are
+    /// taken from the file if it could be looked at, otherwise from the mapping.
+    fn add_anonymous(&mut self, pid: u32, name: String, key: (String, u64), meta: Option<fs::Metadata>, mapped_kb: u64) {
+        let segment = self.anonymous.entry(key).or_insert_with(|| Segment { name, size_kb: 0, rss_kb: None, pids: BTreeSet::new() });
+        match meta {
+            Some(meta) => {
+                segment.size_kb = meta.len() / 1024;
+                segment.rss_kb = Some(meta.blocks() * 512 / 1024);
+            }
+            None if segment.rss_kb.is_none() => segment.size_kb = segment.size_kb.max(mapped_kb),
+            None => {}
+        }
+        segment.pids.insert(pid);
+    }
+}
+
+/// The size of a mapping from its address range, e.g. "7f12a000-7f12c000"
+fn mapping_kb(range: &str) -> u64 {
+    let Some((start, end)) = range.split_once('-') else {
+        return 0;
+    };
+    match (u64::from_str_radix(start, 16), u64::from_str_radix(end, 16)) {
+        (Ok(start), Ok(end)) => end.saturating_sub(start) / 1024,
+        _ => 0,
+    }
+}
+
+fn scan_attachments() -> Attachments {
+    let mut att = Attachments::default();
+    let Ok(proc) = fs::read_dir(procfs::path("/proc")) else {
+        return att;
+    };
+    for entry in proc.flatten() {
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        if !is_numeric_dir(&name) {
+            continue;
+        }
+        let (Ok(pid), Ok(maps)) = (name.parse::<u32>(), fs::read_to_string(procfs::path(&format!("/proc/{name}/maps")))) else {
+            continue;
+        };
+        for line in maps.lines() {
+            // address perms offset dev inode [pathname]
+            let fields: Vec<&str> = line.splitn(6, char::is_whitespace).collect();
+            let (Some(inode), Some(path)) = (fields.get(4), fields.get(5).map(|p| p.trim())) else {
+                continue;
+            };
+            // Private mappings of /dev/zero are ordinary anonymous memory
+            let shared = fields[1].ends_with('s') || path.starts_with("/memfd:");
+            if let Some((anonymous, inode)) = inode.parse().ok().filter(|_| shared).and_then(|i| Some((anonymous_name(path, i)?, i))) {
+                // Resolving map_files needs CAP_SYS_ADMIN
+                let meta = fs::metadata(procfs::path(&format!("/proc/{name}/map_files/{}", fields[0]))).ok();
+                att.add_anonymous(pid, anonymous, (fields[3].to_string(), inode), meta, mapping_kb(fields[0]));
+            } else if path.starts_with("/SYSV") {
+                // The inode of a SysV segment's mapping is its shmid
+                if let Ok(shmid) = inode.parse() {
+                    att.sysv.entry(shmid).or_default().insert(pid);
+                }
+            } else if path.starts_with("/dev/shm/") {
+                let path = path.trim_end_matches(" (deleted)").to_string();
+                att.posix.entry(path).or_default().insert(pid);
+            }
+        }
+        // memfds are often only passed around as file descriptors, e.g. to a
+        // compositor, and mapped later or by another process
+        for fd in fs::read_dir(procfs::path(&format!("/proc/{name}/fd"))).into_iter().flatten().flatten() {
+            let (Ok(target), Ok(meta)) = (fs::read_link(fd.path()), fs::metadata(fd.path())) else {
+                continue;
+            };
+            if let Some(anonymous) = anonymous_name(&target.to_string_lossy(), meta.ino()) {
+                att.add_anonymous(pid, anonymous, (device(meta.dev()), meta.ino()), Some(meta), 0);
+            }
+        }
+    }
+    att
+}
+
+fn sysv_segments(att: &mut Attachments) -> Vec<Segment> {
+    let Ok(data) = fs::read_to_string(procfs::path("/proc/sysvipc/shm")) else {
+        return vec![];
+    };
+    let mut lines = data.lines();
+    let Some(header) = lines.next() else {
+        return vec![];
+    };
+    let columns: Vec<&str> = header.split_whitespace().collect();
+    let column = |name: &str| columns.iter().position(|c| *c == name);
+    let (Some(key), Some(shmid), Some(size), Some(cpid)) =
+        (column("key"), column("shmid"), column("size"), column("cpid"))
+    else {
+        return vec![];
+    };
+    let rss = column("rss");
+
+    let mut segments = vec![];
+    for line in lines {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let get = |i: usize| fields.get(i).and_then(|v| v.parse::<u64>().ok());
+        let (Some(id), Some(bytes)) = (get(shmid), get(size)) else {
+            continue;
+        };
+        let mut pids = att.sysv.remove(&id).unwrap_or_default();
+        // Keep track of the creator even if it has detached, as long as it's alive
+        if let Some(cpid) = get(cpid).filter(|p| fs::metadata(procfs::path(&format!("/proc/{p}"))).is_ok()) {
+            pids.insert(cpid as u32);
+        }
+        let key = fields.get(key).and_then(|k| k.parse::<i64>().ok()).unwrap_or(0);
+        segments.push(Segment {
+            name: format!("sysv: key 0x{:08x} id {id}", key as u32),
+            size_kb: bytes / 1024,
+            rss_kb: rss.and_then(get).map(|b| b / 1024),
+            pids,
+        });
+    }
+    segments
+}
+
+fn posix_segments(att: &mut Attachments) -> Vec<Segment> {
+    let Ok(dir) = fs::read_dir(procfs::path("/dev/shm")) else {
+        return vec![];
+    };
+    let mut segments = vec![];
+    for entry in dir.flatten() {
+        let Ok(meta) = entry.metadata() else {
+            continue;
+        };
+        if !meta.is_file() {
+            continue;
+        }
+        // As in maps, without the root of a snapshot
+        let path = format!("/dev/shm/{}", entry.file_name().to_string_lossy());
+        segments.push(Segment {
+            pids: att.posix.remove(&path).unwrap_or_default(),
+            name: format!("posix: {path}"),
+            size_kb: meta.len() / 1024,
+            // tmpfs only allocates blocks for pages that have been touched
+            rss_kb: Some(meta.blocks() * 512 / 1024),
+        });
+    }
+    segments
+}
+
+pub fn print_shm(out: &mut dyn Write, report: &Report, limit: usize) -> io::Result<()> {
+    let mut att = scan_attachments();
+    let mut segments = sysv_segments(&mut att);
+    segments.extend(posix_segments(&mut att));
+    segments.extend(att.anonymous.into_values());
+    segments.sort_by_key(|s| std::cmp::Reverse(s.rss_kb.unwrap_or(s.size_kb)));
+
+    let group_of: HashMap<u32, &str> = report.samples().map(|p| (p.pid, p.name.as_str())).collect();
+
+    writeln!(out, "{:<40} {:>10} {:>10} {:>5}  Applications", "Segment", "Size(MB)", "RSS(MB)", "Procs")?;
+    for seg in segments.iter().take(limit) {
+        let groups: BTreeSet<&str> = seg.pids.iter().filter_map(|pid| group_of.get(pid).copied()).collect();
+        let rss = seg.rss_kb.map_or("?".to_string(), |kb| format!("{:.2}", kb as f64 / 1024.0));
+        writeln!(
+            out,
+            "{:<40} {:>10.2} {:>10} {:>5}  {}",
+            seg.name,
+            seg.size_kb as f64 / 1024.0,
+            rss,
+            seg.pids.len(),
+            groups.into_iter().collect::<Vec<_>>().join(", ")
+        )?;
+    }
+    let total_kb: u64 = segments.iter().map(|s| s.rss_kb.unwrap_or(s.size_kb)).sum();
+    writeln!(out)?;
+    writeln!(out, "{} segments, {:.2} MB resident", segments.len(), total_kb as f64 / 1024.0)?;
+
+    // Each segment is split evenly between the processes attached to it, so
+    // that memory shared by several applications is only counted once
+    let mut shares: HashMap<&str, (f64, usize)> = HashMap::new();
+    for seg in &segments {
+        let share = seg.rss_kb.unwrap_or(seg.size_kb) as f64 / seg.pids.len() as f64;
+        let groups: BTreeSet<&str> = seg.pids.iter().filter_map(|pid| group_of.get(pid).copied()).collect();
+        for pid in &seg.pids {
+            if let Some(group) = group_of.get(pid) {
+                shares.entry(group).or_default().0 += share;
+            }
+        }
+        for group in groups {
+            shares.entry(group).or_default().1 += 1;
+        }
+    }
+    let mut shares: Vec<_> = shares.into_iter().collect();
+    shares.sort_by(|(a_name, (a, _)), (b_name, (b, _))| b.total_cmp(a).then_with(|| a_name.cmp(b_name)));
+    writeln!(out)?;
+    writeln!(out, "{:<40} {:>10} {:>8}", "Application", "Share(MB)", "Segments")?;
+    for (group, (kb, count)) in shares.iter().take(limit) {
+        writeln!(out, "{:<40} {:>10.2} {:>8}", group, kb / 1024.0, count)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,282 @@
+//! Parses the per-mapping memory statistics in /proc/[pid]/smaps and their
+//! per-process totals in /proc/[pid]/smaps_rollup.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+
+use crate::procfs;
+
+/// One memory mapping of a process; sizes in kB
+#[derive(Default)]
+pub struct Mapping {
+    /// The address range, e.g. "7f12a000-7f12c000"
+    pub address: String,
+    /// Permissions like "r-xp"; the last character is p for private or s for shared
+    pub perms: String,
+    /// The file name, a pseudo name like "[heap]", or empty for anonymous mappings
+    pub pathname: String,
+    /// The size of the address range, resident or not
+    pub size_kb: u64,
+    pub rss_kb: u64,
+    pub pss_kb: u64,
+    pub anonymous_kb: u64,
+    pub swap_kb: u64,
+    /// Anonymous memory in transparent huge pages
+    pub anon_huge_kb: u64,
+    /// Memory in hugetlbfs pages, which is not part of rss_kb
+    pub hugetlb_kb: u64,
+    /// Whether transparent huge pages may be used for the mapping
+    pub thp_eligible: bool,
+}
+
+impl Mapping {
+    /// Whether the mapping is shared memory (POSIX/SysV shm, memfd or shared anonymous)
+    pub fn is_shmem(&self) -> bool {
+        let p = &self.pathname;
+        p.starts_with("/dev/shm/") || p.starts_with("/memfd:") || p.starts_with("/SYSV") || p.starts_with("/dev/zero")
+    }
+
+    /// Whether the mapping is backed by a regular file
+    pub fn is_file(&self) -> bool {
+        self.pathname.starts_with('/') && !self.is_shmem()
+    }
+
+    pub fn is_stack(&self) -> bool {
+        self.pathname == "[stack]"
+    }
+}
+
+/// Resident memory of a process split up by the kind of mapping it belongs to
+#[derive(Clone, Copy, Default)]
+pub struct Breakdown {
+    /// Anonymous memory other than the stack, mostly heap
+    pub anon_kb: u64,
+    pub file_kb: u64,
+    pub shmem_kb: u64,
+    pub stack_kb: u64,
+}
+
+impl std::ops::AddAssign for Breakdown {
+    fn add_assign(&mut self, other: Breakdown) {
+        self.anon_kb += other.anon_kb;
+        self.file_kb += other.file_kb;
+        self.shmem_kb += other.shmem_kb;
+        self.stack_kb += other.stack_kb;
+    }
+}
+
+/// Dirty pages of a process, i.e. pages that need to be written back before
+/// they can be reclaimed (to their file, or to swap if anonymous)
+#[derive(Clone, Copy, Default)]
+pub struct Dirty {
+    pub private_kb: u64,
+    pub shared_kb: u64,
+}
+
+impl std::ops::AddAssign for Dirty {
+    fn add_assign(&mut self, other: Dirty) {
+        self.private_kb += other.private_kb;
+        self.shared_kb += other.shared_kb;
+    }
+}
+
+/// Totals over all mappings of a process from /proc/[pid]/smaps_rollup
+#[derive(Default)]
+pub struct Rollup {
+    /// Proportional set size: resident memory with shared pages divided among their users
+    pub pss_kb: u64,
+    pub private_clean_kb: u64,
+    pub dirty: Dirty,
+}
+
+impl Rollup {
+    /// Unique set size: resident memory no other process shares
+    pub fn uss_kb(&self) -> u64 {
+        self.private_clean_kb + self.dirty.private_kb
+    }
+}
+
+pub fn read_rollup(pid: &str) -> Option<Rollup> {
+    parse_rollup(File::open(procfs::path(&format!("/proc/{pid}/smaps_rollup"))).ok()?)
+}
+
+pub fn parse_rollup(file: impl Read) -> Option<Rollup> {
+    let mut rollup = Rollup::default();
+    for line in BufReader::new(file).lines().map_while(Result::ok) {
+        let Some((key, rest)) = line.split_once(':') else {
+            continue;
+        };
+        let kb = rest.split_whitespace().next().and_then(|v| v.parse::<u64>().ok()).unwrap_or(0);
+        match key {
+            "Pss" => rollup.pss_kb = kb,
+            "Private_Clean" => rollup.private_clean_kb = kb,
+            "Private_Dirty" => rollup.dirty.private_kb = kb,
+            "Shared_Dirty" => rollup.dirty.shared_kb = kb,
+            _ => {}
+        }
+    }
+    Some(rollup)
+}
+
+pub fn read_smaps(pid: &str) -> Option<Vec<Mapping>> {
+    Some(parse_smaps(File::open(procfs::path(&format!("/proc/{pid}/smaps"))).ok()?))
+}
+
+fn parse_smaps(file: impl Read) -> Vec<Mapping> {
+    let mut mappings: Vec<Mapping> = Vec::new();
+    for line in BufReader::new(file).lines().map_while(Result::ok) {
+        let mut fields = line.split_whitespace();
+        let Some(first) = fields.next() else {
+            continue;
+        };
+        if let Some(name) = first.strip_suffix(':') {
+            let Some(mapping) = mappings.last_mut() else {
+                continue;
+            };
+            let kb = fields.next().and_then(|v| v.parse::<u64>().ok()).unwrap_or(0);
+            match name {
+                "Size" => mapping.size_kb = kb,
+                "Rss" => mapping.rss_kb = kb,
+                "Pss" => mapping.pss_kb = kb,
+                "Anonymous" => mapping.anonymous_kb = kb,
+                "Swap" => mapping.swap_kb = kb,
+                "AnonHugePages" => mapping.anon_huge_kb = kb,
+                "Shared_Hugetlb" | "Private_Hugetlb" => mapping.hugetlb_kb += kb,
+                // Not a size, but a flag
+                "THPeligible" => mapping.thp_eligible = kb == 1,
+                _ => {}
+            }
+        } else if first.contains('-') {
+            // Header: address perms offset dev inode [pathname]
+            let fields: Vec<&str> = line.splitn(6, char::is_whitespace).collect();
+            mappings.push(Mapping {
+                address: first.to_string(),
+                perms: fields.get(1).unwrap_or(&"").to_string(),
+                pathname: fields.get(5).unwrap_or(&"").trim().to_string(),
+                ..Default::default()
+            });
+        }
+    }
+    mappings
+}
+
+pub fn breakdown(mappings: &[Mapping]) -> Breakdown {
+    let mut b = Breakdown::default();
+    for m in mappings {
+        if m.is_stack() {
+            b.stack_kb += m.rss_kb;
+        } else if m.is_shmem() {
+            b.shmem_kb += m.rss_kb;
+        } else {
+            // Private file mappings contain anonymous copy-on-write pages
+            b.anon_kb += m.anonymous_kb;
+            b.file_kb += m.rss_kb.saturating_sub(m.anonymous_kb);
+        }
+    }
+    b
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SMAPS: &str = "\
+55d0c0a00000-55d0c0a21000 r--p 00000000 fd:01 1234                       /usr/bin/cat
+Size:                132 kB
+Rss:                 100 kB
+Pss:                  50 kB
+Anonymous:             8 kB
+Swap:                  0 kB
+THPeligible:    0
+55d0c1000000-55d0c1200000 rw-p 00000000 00:00 0                          [heap]
+Size:               2048 kB
+Rss:                1024 kB
+Pss:                1024 kB
+Anonymous:          1024 kB
+Swap:                 16 kB
+AnonHugePages:      1024 kB
+THPeligible:    1
+7f0000000000-7f0000100000 rw-s 00000000 00:01 99                         /dev/shm/pulse-shm-1 (deleted)
+Size:               1024 kB
+Rss:                 200 kB
+Pss:                 100 kB
+Anonymous:             0 kB
+7f0000200000-7f0000400000 rw-s 00000000 00:0f 7                          /anon_hugepage (deleted)
+Size:               2048 kB
+Rss:                   0 kB
+Shared_Hugetlb:     2048 kB
+Private_Hugetlb:    2048 kB
+7f0000400000-7f0000500000 rw-p 00000000 00:00 0 
+Size:               1024 kB
+Rss:                 300 kB
+Anonymous:           300 kB
+7ffc00000000-7ffc00021000 rw-p 00000000 00:00 0                          [stack]
+Size:                132 kB
+Rss:                  40 kB
+Anonymous:            40 kB
+";
+
+    #[test]
+    fn parses_mappings() {
+        let mappings = parse_smaps(SMAPS.as_bytes());
+        assert_eq!(mappings.len(), 6);
+        let cat = &mappings[0];
+        assert_eq!((cat.address.as_str(), cat.perms.as_str(), cat.pathname.as_str()), ("55d0c0a00000-55d0c0a21000", "r--p", "/usr/bin/cat"));
+        assert_eq!((cat.size_kb, cat.rss_kb, cat.pss_kb, cat.anonymous_kb), (132, 100, 50, 8));
+        assert!(cat.is_file() && !cat.thp_eligible);
+        let heap = &mappings[1];
+        assert_eq!((heap.swap_kb, heap.anon_huge_kb), (16, 1024));
+        assert!(heap.thp_eligible && !heap.is_file());
+        // Names with spaces are kept whole
+        assert_eq!(mappings[2].pathname, "/dev/shm/pulse-shm-1 (deleted)");
+        assert!(mappings[2].is_shmem());
+        assert_eq!(mappings[3].hugetlb_kb, 4096);
+        // Anonymous mappings have no name, only trailing whitespace
+        assert_eq!(mappings[4].pathname, "");
+        assert!(mappings[5].is_stack());
+    }
+
+    #[test]
+    fn breaks_down_by_kind() {
+        let b = breakdown(&parse_smaps(SMAPS.as_bytes()));
+        // The private copies of the file mapping count as anonymous
+        assert_eq!(b.anon_kb, 8 + 1024 + 300);
+        assert_eq!(b.file_kb, 100 - 8);
+        assert_eq!(b.shmem_kb, 200);
+        assert_eq!(b.stack_kb, 40);
+        assert_eq!(breakdown(&[]).anon_kb, 0);
+    }
+
+    #[test]
+    fn classifies_shared_memory() {
+        let mapping = |pathname: &str| Mapping { pathname: pathname.to_string(), ..Default::default() };
+        for shmem in ["/dev/shm/x", "/memfd:wayland-cursor (deleted)", "/SYSV00000000 (deleted)", "/dev/zero (deleted)"] {
+            assert!(mapping(shmem).is_shmem(), "{shmem}");
+            assert!(!mapping(shmem).is_file(), "{shmem}");
+        }
+        assert!(mapping("/usr/lib/libc.so.6").is_file());
+        assert!(!mapping("[vdso]").is_file());
+    }
+
+    #[test]
+    fn parses_rollup() {
+        let text = "\
+55d0c0a00000-7ffc00021000 ---p 00000000 00:00 0                          [rollup]
+Rss:                5000 kB
+Pss:                3000 kB
+Pss_Anon:           2000 kB
+Shared_Clean:       1500 kB
+Shared_Dirty:        100 kB
+Private_Clean:       400 kB
+Private_Dirty:      2000 kB
+Swap:                  0 kB
+";
+        let rollup = parse_rollup(text.as_bytes()).unwrap();
+        // Pss_Anon and the like don't get mixed up with Pss
+        assert_eq!(rollup.pss_kb, 3000);
+        assert_eq!((rollup.dirty.private_kb, rollup.dirty.shared_kb), (2000, 100));
+        assert_eq!(rollup.uss_kb(), 2400);
+        let empty = parse_rollup("".as_bytes()).unwrap();
+        assert_eq!((empty.pss_kb, empty.uss_kb()), (0, 0));
+    }
+}
@@ -0,0 +1,71 @@
+//! The `swap` subcommand: which applications have been paged out.
+
+use std::fs;
+use std::io::{self, Write};
+
+use crate::model::Report;
+use crate::procfs::{self, read_meminfo};
+
+/// A swap device or file from /proc/swaps; sizes in kB
+struct SwapDevice {
+    name: String,
+    size_kb: u64,
+    used_kb: u64,
+}
+
+fn read_swaps() -> Vec<SwapDevice> {
+    let Ok(data) = fs::read_to_string(procfs::path("/proc/swaps")) else {
+        return vec![];
+    };
+    data.lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            Some(SwapDevice {
+                name: fields.first()?.to_string(),
+                size_kb: fields.get(2)?.parse().ok()?,
+                used_kb: fields.get(3)?.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Prints groups ordered by swap usage; expects the report to be sorted by swap
+pub fn print_swap(out: &mut dyn Write, report: &Report, limit: usize) -> io::Result<()> {
+    let meminfo = read_meminfo();
+    let total_kb = meminfo.get("SwapTotal").copied().unwrap_or(0);
+    let used_kb = total_kb.saturating_sub(meminfo.get("SwapFree").copied().unwrap_or(0));
+    let mb = |kb: u64| kb as f64 / 1024.0;
+
+    if total_kb == 0 {
+        writeln!(out, "No swap configured")?;
+    } else {
+        writeln!(
+            out,
+            "Swap: {:.2} MB used of {:.2} MB ({:.1}%)",
+            mb(used_kb),
+            mb(total_kb),
+            used_kb as f64 * 100.0 / total_kb as f64
+        )?;
+        for dev in read_swaps() {
+            writeln!(out, "  {:<40} {:>10.2} MB used of {:>10.2} MB", dev.name, mb(dev.used_kb), mb(dev.size_kb))?;
+        }
+    }
+    writeln!(out)?;
+
+    writeln!(out, "{:<35} {:>4} {:>10} {:>8} {:>12}", "Application", "Num", "Swap(MB)", "%", "Memory(MB)")?;
+    for group in report.groups.iter().filter(|g| g.swap_kb > 0).take(limit) {
+        // Share of the swap that is in use, not of its capacity
+        let pct = if used_kb > 0 { group.swap_kb as f64 * 100.0 / used_kb as f64 } else { 0.0 };
+        writeln!(
+            out,
+            "{:<35} {:>4} {:>10.2} {:>7.2}% {:>12.2}",
+            group.name,
+            group.num(),
+            mb(group.swap_kb),
+            pct,
+            mb(group.rss_kb)
+        )?;
+    }
+    Ok(())
+}
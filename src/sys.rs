@@ -0,0 +1,303 @@
+//! Declarations for the few libc functions not covered by std. The standard
+//! library links against libc anyway, so no extra dependency is needed. The
+//! structures and constants are those of Linux on x86_64 and aarch64, the
+//! only targets this module is built for.
+
+use std::ffi::{CString, OsString};
+use std::fs::File;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::raw::{c_char, c_int, c_long, c_short, c_uint, c_ulong, c_ushort, c_void};
+use std::os::unix::ffi::OsStringExt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// struct statvfs as defined by glibc and musl on 64-bit Linux
+#[repr(C)]
+#[derive(Default)]
+pub struct StatVfs {
+    pub f_bsize: c_ulong,
+    pub f_frsize: c_ulong,
+    pub f_blocks: u64,
+    pub f_bfree: u64,
+    pub f_bavail: u64,
+    pub f_files: u64,
+    pub f_ffree: u64,
+    pub f_favail: u64,
+    pub f_fsid: c_ulong,
+    pub f_flag: c_ulong,
+    pub f_namemax: c_ulong,
+    __f_spare: [c_int; 6],
+}
+
+/// struct termios as defined by glibc and musl on Linux
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Termios {
+    c_iflag: c_uint,
+    c_oflag: c_uint,
+    c_cflag: c_uint,
+    c_lflag: c_uint,
+    c_line: u8,
+    c_cc: [u8; 32],
+    c_ispeed: c_uint,
+    c_ospeed: c_uint,
+}
+
+/// struct tm as defined by glibc and musl
+#[repr(C)]
+struct Tm {
+    tm_sec: c_int,
+    tm_min: c_int,
+    tm_hour: c_int,
+    tm_mday: c_int,
+    tm_mon: c_int,
+    tm_year: c_int,
+    tm_wday: c_int,
+    tm_yday: c_int,
+    tm_isdst: c_int,
+    tm_gmtoff: c_long,
+    tm_zone: *const c_char,
+}
+
+#[repr(C)]
+struct PollFd {
+    fd: c_int,
+    events: c_short,
+    revents: c_short,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct WinSize {
+    ws_row: c_ushort,
+    ws_col: c_ushort,
+    ws_xpixel: c_ushort,
+    ws_ypixel: c_ushort,
+}
+
+const PROT_READ: c_int = 1;
+const MAP_SHARED: c_int = 1;
+const SC_PAGESIZE: c_int = 30;
+const ICANON: c_uint = 0o2;
+const ECHO: c_uint = 0o10;
+const VTIME: usize = 5;
+const VMIN: usize = 6;
+const TCSANOW: c_int = 0;
+const POLLIN: c_short = 1;
+const TIOCGWINSZ: c_ulong = 0x5413;
+const SIG_IGN: usize = 1;
+/// O_RDONLY | O_CLOEXEC on Linux
+const O_RDONLY_CLOEXEC: c_int = 0o2000000;
+
+pub const SIGINT: c_int = 2;
+pub const SIGQUIT: c_int = 3;
+pub const SIGKILL: c_int = 9;
+pub const SIGTERM: c_int = 15;
+
+unsafe extern "C" {
+    fn statvfs(path: *const c_char, buf: *mut StatVfs) -> c_int;
+    fn mmap(addr: *mut c_void, len: usize, prot: c_int, flags: c_int, fd: c_int, offset: i64) -> *mut c_void;
+    fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    fn mincore(addr: *mut c_void, len: usize, vec: *mut u8) -> c_int;
+    fn sysconf(name: c_int) -> c_long;
+    fn tcgetattr(fd: c_int, termios: *mut Termios) -> c_int;
+    fn tcsetattr(fd: c_int, action: c_int, termios: *const Termios) -> c_int;
+    fn poll(fds: *mut PollFd, nfds: c_ulong, timeout: c_int) -> c_int;
+    fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+    fn kill(pid: c_int, sig: c_int) -> c_int;
+    fn signal(signum: c_int, handler: usize) -> usize;
+    fn localtime_r(time: *const i64, result: *mut Tm) -> *mut Tm;
+    fn openat(dirfd: c_int, path: *const c_char, flags: c_int, ...) -> c_int;
+    fn readlinkat(dirfd: c_int, path: *const c_char, buf: *mut c_char, len: usize) -> isize;
+}
+
+pub fn stat_vfs(path: &str) -> io::Result<StatVfs> {
+    let path = CString::new(path).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    let mut buf = StatVfs::default();
+    // SAFETY: path is NUL-terminated and buf is a properly sized, writable struct statvfs
+    if unsafe { statvfs(path.as_ptr(), &mut buf) } == 0 {
+        Ok(buf)
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Opens the file name relative to the directory dir for reading
+pub fn open_at(dir: &File, name: &str) -> io::Result<File> {
+    let name = CString::new(name).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    // SAFETY: name is NUL-terminated; the descriptor returned is owned by nothing else
+    let fd = unsafe { openat(dir.as_raw_fd(), name.as_ptr(), O_RDONLY_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: fd is a freshly opened descriptor that the File takes ownership of
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+/// Reads the target of the symbolic link name relative to the directory dir
+pub fn read_link_at(dir: &File, name: &str) -> io::Result<PathBuf> {
+    let name = CString::new(name).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    let mut buf = vec![0u8; 4096];
+    // SAFETY: name is NUL-terminated and buf is writable for buf.len() bytes
+    let len = unsafe { readlinkat(dir.as_raw_fd(), name.as_ptr(), buf.as_mut_ptr().cast(), buf.len()) };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    buf.truncate(len as usize);
+    Ok(PathBuf::from(OsString::from_vec(buf)))
+}
+
+pub fn page_size() -> usize {
+    // SAFETY: sysconf has no preconditions
+    let size = unsafe { sysconf(SC_PAGESIZE) };
+    if size > 0 { size as usize } else { 4096 }
+}
+
+/// Counts how many of the first len bytes of file are in the page cache.
+/// Returns (total pages, resident pages).
+pub fn resident_pages(file: &File, len: usize) -> io::Result<(usize, usize)> {
+    if len == 0 {
+        return Ok((0, 0));
+    }
+    let page = page_size();
+    let pages = len.div_ceil(page);
+    // SAFETY: a fresh read-only shared mapping of an open file; nothing else refers to it
+    let addr = unsafe { mmap(std::ptr::null_mut(), len, PROT_READ, MAP_SHARED, file.as_raw_fd(), 0) };
+    if addr as isize == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut vec = vec![0u8; pages];
+    // SAFETY: addr..addr+len is our mapping and vec holds one byte per page of it
+    let result = unsafe { mincore(addr, len, vec.as_mut_ptr()) };
+    let err = io::Error::last_os_error();
+    // SAFETY: unmaps exactly the mapping created above
+    unsafe { munmap(addr, len) };
+    if result != 0 {
+        return Err(err);
+    }
+    Ok((pages, vec.iter().filter(|b| *b & 1 != 0).count()))
+}
+
+/// Puts the terminal on stdin into non-canonical mode without echo, so that
+/// single key presses can be read. The previous mode is restored on drop.
+pub struct RawMode {
+    saved: Termios,
+}
+
+impl RawMode {
+    pub fn enter() -> io::Result<RawMode> {
+        // SAFETY: Termios is plain data; tcgetattr fully initializes it on success
+        let mut saved: Termios = unsafe { std::mem::zeroed() };
+        // SAFETY: fd 0 and a valid, writable Termios
+        if unsafe { tcgetattr(0, &mut saved) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut raw = saved;
+        raw.c_lflag &= !(ICANON | ECHO);
+        raw.c_cc[VMIN] = 1;
+        raw.c_cc[VTIME] = 0;
+        // SAFETY: fd 0 and a valid Termios
+        if unsafe { tcsetattr(0, TCSANOW, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(RawMode { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // SAFETY: restores the settings read in enter()
+        unsafe { tcsetattr(0, TCSANOW, &self.saved) };
+    }
+}
+
+/// Waits until stdin has input or the timeout expires; returns whether input is available
+pub fn wait_for_input(timeout: Duration) -> io::Result<bool> {
+    let mut fd = PollFd { fd: 0, events: POLLIN, revents: 0 };
+    let ms = timeout.as_millis().min(c_int::MAX as u128) as c_int;
+    // SAFETY: a single valid pollfd
+    match unsafe { poll(&mut fd, 1, ms) } {
+        -1 => {
+            let err = io::Error::last_os_error();
+            // A signal such as SIGWINCH is not an error
+            if err.kind() == io::ErrorKind::Interrupted { Ok(false) } else { Err(err) }
+        }
+        n => Ok(n > 0),
+    }
+}
+
+/// The size of the terminal on stdout as (rows, columns)
+pub fn terminal_size() -> Option<(usize, usize)> {
+    let mut ws = WinSize::default();
+    // SAFETY: TIOCGWINSZ writes a struct winsize
+    if unsafe { ioctl(1, TIOCGWINSZ, &mut ws as *mut WinSize) } == 0 && ws.ws_row > 0 {
+        Some((ws.ws_row as usize, ws.ws_col as usize))
+    } else {
+        None
+    }
+}
+
+pub fn send_signal(pid: u32, signal: c_int) -> io::Result<()> {
+    // SAFETY: kill has no memory safety preconditions
+    if unsafe { kill(pid as c_int, signal) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Makes the process ignore a signal, e.g. SIGINT while a child handles Ctrl-C
+pub fn ignore_signal(sig: c_int) {
+    // SAFETY: SIG_IGN installs no handler code, so nothing runs in signal context
+    unsafe { signal(sig, SIG_IGN) };
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_sig: c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Makes Ctrl-C set a flag instead of terminating the process; see interrupted()
+pub fn catch_interrupt() {
+    // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+    unsafe { signal(SIGINT, on_interrupt as extern "C" fn(c_int) as usize) };
+}
+
+/// Whether Ctrl-C was pressed since catch_interrupt()
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Formats seconds since the Unix epoch as "YYYY-MM-DD HH:MM:SS" in local time
+pub fn format_local_time(secs: u64) -> String {
+    let time = secs as i64;
+    let mut tm = Tm {
+        tm_sec: 0,
+        tm_min: 0,
+        tm_hour: 0,
+        tm_mday: 0,
+        tm_mon: 0,
+        tm_year: 0,
+        tm_wday: 0,
+        tm_yday: 0,
+        tm_isdst: 0,
+        tm_gmtoff: 0,
+        tm_zone: std::ptr::null(),
+    };
+    // SAFETY: both pointers refer to valid, properly sized values for the duration of the call
+    if unsafe { localtime_r(&time, &mut tm) }.is_null() {
+        return secs.to_string();
+    }
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec
+    )
+}
@@ -0,0 +1,126 @@
+//! The `tmpfs` subcommand: memory consumed by files in RAM-backed filesystems.
+//!
+//! Files in tmpfs or ramfs live in memory (or swap) without belonging to any
+//! process, so they don't show up in the process report at all.
+
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use crate::procfs::read_meminfo;
+use crate::sys::stat_vfs;
+
+struct Mount {
+    path: String,
+    fstype: String,
+    size_kb: Option<u64>,
+    used_kb: u64,
+}
+
+/// Decodes the octal escapes (\040 for space etc.) used in /proc/mounts
+fn unescape(field: &str) -> String {
+    let mut out = Vec::new();
+    let bytes = field.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\'
+            && let Some(b) = field.get(i + 1..i + 4).and_then(|o| u8::from_str_radix(o, 8).ok())
+        {
+            out.push(b);
+            i += 4;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+/// Sums the allocated size of all files below dir without crossing into other filesystems
+fn du_kb(dir: &Path, dev: u64) -> u64 {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    let mut kb = 0;
+    for entry in entries.flatten() {
+        let Ok(meta) = entry.metadata() else {
+            continue;
+        };
+        if meta.dev() != dev {
+            continue;
+        }
+        if meta.is_dir() {
+            kb += du_kb(&entry.path(), dev);
+        } else if meta.is_file() {
+            kb += meta.blocks() * 512 / 1024;
+        }
+    }
+    kb
+}
+
+fn ram_mounts() -> Vec<Mount> {
+    let Ok(data) = fs::read_to_string("/proc/mounts") else {
+        return vec![];
+    };
+    let mut mounts: Vec<Mount> = vec![];
+    for line in data.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (Some(path), Some(fstype)) = (fields.get(1), fields.get(2)) else {
+            continue;
+        };
+        let fstype = fstype.to_string();
+        if fstype != "tmpfs" && fstype != "ramfs" && fstype != "devtmpfs" {
+            continue;
+        }
+        let path = unescape(path);
+        // A mount point may have been mounted over; only the last mount is visible
+        mounts.retain(|m| m.path != path);
+
+        let mount = if fstype == "ramfs" {
+            // ramfs has neither a size limit nor block accounting
+            let dev = fs::metadata(&path).map(|m| m.dev()).unwrap_or(0);
+            Mount { used_kb: du_kb(Path::new(&path), dev), path, fstype, size_kb: None }
+        } else {
+            let Ok(st) = stat_vfs(&path) else {
+                continue;
+            };
+            let block_kb = st.f_frsize / 1024;
+            Mount {
+                path,
+                fstype,
+                size_kb: Some(st.f_blocks * block_kb),
+                used_kb: st.f_blocks.saturating_sub(st.f_bfree) * block_kb,
+            }
+        };
+        mounts.push(mount);
+    }
+    mounts
+}
+
+pub fn print_tmpfs(out: &mut dyn Write, limit: usize) -> io::Result<()> {
+    let mut mounts = ram_mounts();
+    mounts.sort_by_key(|m| std::cmp::Reverse(m.used_kb));
+
+    writeln!(out, "{:<40} {:<9} {:>10} {:>10} {:>6}", "Mount", "Type", "Size(MB)", "Used(MB)", "Use%")?;
+    for m in mounts.iter().take(limit) {
+        let (size, pct) = match m.size_kb {
+            Some(size) if size > 0 => (
+                format!("{:.2}", size as f64 / 1024.0),
+                format!("{:.1}%", m.used_kb as f64 * 100.0 / size as f64),
+            ),
+            _ => ("-".to_string(), "-".to_string()),
+        };
+        writeln!(out, "{:<40} {:<9} {:>10} {:>10.2} {:>6}", m.path, m.fstype, size, m.used_kb as f64 / 1024.0, pct)?;
+    }
+
+    let used_kb: u64 = mounts.iter().map(|m| m.used_kb).sum();
+    let meminfo = read_meminfo();
+    writeln!(out)?;
+    writeln!(
+        out,
+        "{:.2} MB in RAM-backed filesystems; {:.2} MB shared memory system-wide (Shmem, includes tmpfs)",
+        used_kb as f64 / 1024.0,
+        meminfo.get("Shmem").copied().unwrap_or(0) as f64 / 1024.0
+    )
+}
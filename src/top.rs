@@ -0,0 +1,302 @@
+//! The `top` subcommand: a continuously updated report in which groups and
+//! their processes can be selected, sent signals, or have their OOM score
+//! adjusted, so that memory problems can be remedied right where they are found.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::mem::ManuallyDrop;
+use std::os::fd::FromRawFd;
+use std::time::{Duration, Instant};
+
+use crate::model::Report;
+use crate::output::print_pressure;
+use crate::procfs;
+use crate::sys::{self, RawMode, SIGKILL, SIGTERM};
+
+const HELP: &str = "↑/↓ select  Enter expand  t SIGTERM  K SIGKILL  o oom_score_adj  q quit";
+/// Lines above the table: pressure, blank line, help, column headers
+const HEADER_LINES: usize = 4;
+/// Lines below the table: blank line, message or prompt
+const FOOTER_LINES: usize = 2;
+
+/// A selectable line: a group, or one process of the expanded group
+#[derive(Clone, PartialEq)]
+enum Selection {
+    Group(String),
+    Process(u32),
+}
+
+enum Prompt {
+    /// Waiting for y/n before sending a signal
+    Confirm { signal: i32, pids: Vec<u32>, what: String },
+    /// Reading a new oom_score_adj value
+    OomAdj { pids: Vec<u32>, what: String, input: String },
+}
+
+#[derive(Default)]
+struct State {
+    selected: Option<Selection>,
+    /// The group whose processes are listed below it
+    expanded: Option<String>,
+    /// Index of the first row shown
+    offset: usize,
+    prompt: Option<Prompt>,
+    message: String,
+    /// Set after an action to update the report right away
+    refresh: bool,
+}
+
+enum Key {
+    Up,
+    Down,
+    Enter,
+    Backspace,
+    Escape,
+    Char(char),
+}
+
+fn parse_keys(buf: &[u8]) -> Vec<Key> {
+    let mut keys = vec![];
+    let mut i = 0;
+    while i < buf.len() {
+        let key = match &buf[i..] {
+            [0x1b, b'[', b'A', ..] | [0x1b, b'O', b'A', ..] => Some((Key::Up, 3)),
+            [0x1b, b'[', b'B', ..] | [0x1b, b'O', b'B', ..] => Some((Key::Down, 3)),
+            [0x1b, b'[', ..] | [0x1b, b'O', ..] => {
+                // Skip other escape sequences up to their final byte
+                let len = buf[i + 2..].iter().position(|b| (0x40..=0x7e).contains(b)).map_or(buf.len() - i, |p| p + 3);
+                i += len;
+                None
+            }
+            [0x1b, ..] => Some((Key::Escape, 1)),
+            [b'\r' | b'\n', ..] => Some((Key::Enter, 1)),
+            [0x7f | 0x08, ..] => Some((Key::Backspace, 1)),
+            [b, ..] => Some((Key::Char(*b as char), 1)),
+            [] => None,
+        };
+        if let Some((key, len)) = key {
+            keys.push(key);
+            i += len;
+        }
+    }
+    keys
+}
+
+/// The selectable rows in display order
+fn rows(report: &Report, state: &State) -> Vec<Selection> {
+    let mut rows = vec![];
+    for group in &report.groups {
+        rows.push(Selection::Group(group.name.clone()));
+        if state.expanded.as_ref() == Some(&group.name) {
+            let mut processes: Vec<_> = group.processes.iter().collect();
+            processes.sort_by_key(|p| std::cmp::Reverse(p.rss_kb));
+            rows.extend(processes.iter().map(|p| Selection::Process(p.pid)));
+        }
+    }
+    rows
+}
+
+/// The processes an action on the selection applies to, and how to describe them
+fn targets(report: &Report, selection: &Selection) -> Option<(Vec<u32>, String)> {
+    match selection {
+        Selection::Group(name) => {
+            let group = report.groups.iter().find(|g| &g.name == name)?;
+            let pids = group.processes.iter().map(|p| p.pid).collect();
+            Some((pids, format!("{} ({} processes)", group.name, group.num())))
+        }
+        Selection::Process(pid) => {
+            let p = report.samples().find(|p| p.pid == *pid)?;
+            Some((vec![*pid], format!("PID {pid} ({})", p.name)))
+        }
+    }
+}
+
+fn signal_name(signal: i32) -> &'static str {
+    if signal == SIGKILL { "SIGKILL" } else { "SIGTERM" }
+}
+
+/// Applies f to every pid and summarizes the outcome
+fn apply(pids: &[u32], done: &str, f: impl Fn(u32) -> io::Result<()>) -> String {
+    let errors: Vec<String> = pids.iter().filter_map(|pid| f(*pid).err().map(|e| format!("{pid}: {e}"))).collect();
+    if errors.is_empty() {
+        format!("{done} {} process(es)", pids.len())
+    } else {
+        format!("Failed for {} of {} process(es): {}", errors.len(), pids.len(), errors.join(", "))
+    }
+}
+
+/// Handles a key press; returns false to quit
+fn handle_key(key: Key, state: &mut State, rows: &[Selection], report: &Report) -> bool {
+    match state.prompt.take() {
+        Some(Prompt::Confirm { signal, pids, what }) => {
+            state.message = match key {
+                Key::Char('y' | 'Y') => {
+                    state.refresh = true;
+                    apply(&pids, &format!("Sent {} to", signal_name(signal)), |pid| sys::send_signal(pid, signal))
+                }
+                _ => format!("Not sending {} to {what}", signal_name(signal)),
+            };
+        }
+        Some(Prompt::OomAdj { pids, what, mut input }) => match key {
+            Key::Char(c) if c.is_ascii_digit() || (c == '-' && input.is_empty()) => {
+                input.push(c);
+                state.prompt = Some(Prompt::OomAdj { pids, what, input });
+            }
+            Key::Backspace => {
+                input.pop();
+                state.prompt = Some(Prompt::OomAdj { pids, what, input });
+            }
+            Key::Enter => {
+                state.message = match input.parse::<i32>() {
+                    Ok(adj) if (-1000..=1000).contains(&adj) => {
+                        state.refresh = true;
+                        apply(&pids, &format!("Set oom_score_adj to {adj} for"), |pid| {
+                            fs::write(format!("/proc/{pid}/oom_score_adj"), adj.to_string())
+                        })
+                    }
+                    _ => format!("Invalid oom_score_adj: {input} (must be between -1000 and 1000)"),
+                };
+            }
+            _ => state.message = "Cancelled".to_string(),
+        },
+        None => {
+            let index = rows.iter().position(|r| Some(r) == state.selected.as_ref()).unwrap_or(0);
+            match key {
+                Key::Char('q') => return false,
+                Key::Up => state.selected = rows.get(index.saturating_sub(1)).cloned(),
+                Key::Down => state.selected = rows.get((index + 1).min(rows.len().saturating_sub(1))).cloned(),
+                Key::Enter => {
+                    let group = match &state.selected {
+                        Some(Selection::Group(name)) => Some(name.clone()),
+                        Some(Selection::Process(pid)) => report.samples().find(|p| p.pid == *pid).map(|p| p.name.clone()),
+                        None => None,
+                    };
+                    if state.expanded.is_some() && state.expanded == group {
+                        state.expanded = None;
+                        state.selected = group.map(Selection::Group);
+                    } else {
+                        state.expanded = group;
+                    }
+                }
+                Key::Char(c @ ('t' | 'K' | 'o')) => {
+                    if let Some((pids, what)) = state.selected.as_ref().and_then(|s| targets(report, s)) {
+                        state.prompt = Some(match c {
+                            'o' => Prompt::OomAdj { pids, what, input: String::new() },
+                            't' => Prompt::Confirm { signal: SIGTERM, pids, what },
+                            _ => Prompt::Confirm { signal: SIGKILL, pids, what },
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    true
+}
+
+fn draw(out: &mut dyn Write, report: &Report, rows: &[Selection], state: &mut State) -> io::Result<()> {
+    let (height, width) = sys::terminal_size().unwrap_or((24, 80));
+    let visible = height.saturating_sub(HEADER_LINES + FOOTER_LINES).max(1);
+    let selected = rows.iter().position(|r| Some(r) == state.selected.as_ref()).unwrap_or(0);
+    if selected < state.offset {
+        state.offset = selected;
+    } else if selected >= state.offset + visible {
+        state.offset = selected + 1 - visible;
+    }
+
+    let total_kb = report.mem_total_kb.max(1) as f64;
+    let mut frame = Vec::new();
+    frame.extend_from_slice(b"\x1b[H\x1b[2J");
+    print_pressure(&mut frame, report, true)?;
+    writeln!(frame, "{HELP}")?;
+    writeln!(frame, "{:<35} {:>5} {:>12} {:>8} {:>10}", "Application", "Num", "Memory(MB)", "%", "Swap(MB)")?;
+    for (i, row) in rows.iter().enumerate().skip(state.offset).take(visible) {
+        let line = match row {
+            Selection::Group(name) => {
+                let Some(g) = report.groups.iter().find(|g| &g.name == name) else {
+                    continue;
+                };
+                let pct = g.rss_kb as f64 * 100.0 / total_kb;
+                format!("{:<35} {:>5} {:>12.2} {:>7.2}% {:>10.2}", g.name, g.num(), g.rss_kb as f64 / 1024.0, pct, g.swap_kb as f64 / 1024.0)
+            }
+            Selection::Process(pid) => {
+                let Some(p) = report.samples().find(|p| p.pid == *pid) else {
+                    continue;
+                };
+                let pct = p.rss_kb as f64 * 100.0 / total_kb;
+                let user: String = p.user.chars().take(23).collect();
+                format!("  └ {:<7} {:<23} {:>5} {:>12.2} {:>7.2}% {:>10.2}", pid, user, "", p.rss_kb as f64 / 1024.0, pct, p.swap_kb as f64 / 1024.0)
+            }
+        };
+        let line: String = line.chars().take(width).collect();
+        if i == selected {
+            writeln!(frame, "\x1b[7m{line:<width$}\x1b[0m")?;
+        } else {
+            writeln!(frame, "{line}")?;
+        }
+    }
+
+    let footer = match &state.prompt {
+        Some(Prompt::Confirm { signal, what, .. }) => format!("Send {} to {what}? [y/N]", signal_name(*signal)),
+        Some(Prompt::OomAdj { what, input, .. }) => format!("oom_score_adj for {what} (-1000..1000): {input}"),
+        None => state.message.clone(),
+    };
+    write!(frame, "\x1b[{height};1H{}", footer.chars().take(width).collect::<String>())?;
+    out.write_all(&frame)?;
+    out.flush()
+}
+
+/// Runs the interactive view until the user quits, rebuilding the report every interval
+pub fn run(build: impl FnMut() -> Report, interval: Duration) -> io::Result<()> {
+    let raw = RawMode::enter()?;
+    // Leave the loop on Ctrl-C rather than dying with the terminal still raw
+    sys::catch_interrupt();
+    let mut out = io::stdout();
+    // Switch to the alternate screen and hide the cursor
+    write!(out, "\x1b[?1049h\x1b[?25l")?;
+    // Building a report exits through procfs::exit on errors, which runs this as well
+    procfs::set_exit_hook(Box::new(move || {
+        let mut out = io::stdout();
+        let _ = write!(out, "\x1b[?25h\x1b[?1049l");
+        let _ = out.flush();
+        drop(raw);
+    }));
+    let result = event_loop(&mut out, build, interval);
+    procfs::run_exit_hook();
+    result
+}
+
+fn event_loop(out: &mut dyn Write, mut build: impl FnMut() -> Report, interval: Duration) -> io::Result<()> {
+    // Read stdin unbuffered, so that poll() sees every pending key press
+    // SAFETY: fd 0 stays open for the lifetime of the process and is never closed here
+    let mut stdin = ManuallyDrop::new(unsafe { File::from_raw_fd(0) });
+    let mut state = State::default();
+    let mut report = build();
+    let mut updated = Instant::now();
+    while !sys::interrupted() {
+        let rows = rows(&report, &state);
+        if !state.selected.as_ref().is_some_and(|s| rows.contains(s)) {
+            state.selected = rows.first().cloned();
+        }
+        draw(out, &report, &rows, &mut state)?;
+
+        if sys::wait_for_input(interval.saturating_sub(updated.elapsed()))? {
+            let mut buf = [0u8; 64];
+            let n = stdin.read(&mut buf)?;
+            if n == 0 {
+                return Ok(());
+            }
+            for key in parse_keys(&buf[..n]) {
+                if !handle_key(key, &mut state, &rows, &report) {
+                    return Ok(());
+                }
+            }
+        }
+        if state.refresh || updated.elapsed() >= interval {
+            report = build();
+            updated = Instant::now();
+            state.refresh = false;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,234 @@
+//! Renders memory usage as a squarified treemap in SVG format.
+
+use std::fmt::Write;
+
+const WIDTH: f64 = 1200.0;
+const HEIGHT: f64 = 800.0;
+/// Height of the label strip at the top of a nested (parent) rectangle
+const HEADER: f64 = 18.0;
+const PADDING: f64 = 2.0;
+
+/// A labelled value in the treemap, optionally subdivided into children
+pub struct Node {
+    pub label: String,
+    pub kb: u64,
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    pub fn leaf(label: String, kb: u64) -> Node {
+        Node { label, kb, children: vec![] }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Rect {
+    x: f64,
+    y: f64,
+    w: f64,
+    h: f64,
+}
+
+/// Produces a complete SVG document for the given top-level nodes
+pub fn render(nodes: &[Node]) -> String {
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{HEIGHT}" viewBox="0 0 {WIDTH} {HEIGHT}" font-family="sans-serif" font-size="12">"#
+    );
+    let _ = writeln!(svg, r##"<rect width="{WIDTH}" height="{HEIGHT}" fill="#ffffff"/>"##);
+    render_level(&mut svg, nodes, Rect { x: 0.0, y: 0.0, w: WIDTH, h: HEIGHT });
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn render_level(svg: &mut String, nodes: &[Node], area: Rect) {
+    let values: Vec<f64> = nodes.iter().map(|n| n.kb as f64).collect();
+    for (node, rect) in nodes.iter().zip(squarify(&values, area)) {
+        if rect.w < 1.0 || rect.h < 1.0 {
+            continue;
+        }
+        let title = format!("{} ({:.2} MB)", escape(&node.label), node.kb as f64 / 1024.0);
+        if node.children.is_empty() {
+            let _ = writeln!(
+                svg,
+                r##"<g><title>{title}</title><rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="{}" stroke="#ffffff"/>"##,
+                rect.x, rect.y, rect.w, rect.h, color(&node.label)
+            );
+            label(svg, &title, rect, 14.0);
+            svg.push_str("</g>\n");
+        } else {
+            let _ = writeln!(
+                svg,
+                r##"<g><title>{title}</title><rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="#555555" stroke="#ffffff"/>"##,
+                rect.x, rect.y, rect.w, rect.h
+            );
+            label(svg, &title, rect, HEADER - 4.0);
+            svg.push_str("</g>\n");
+            let inner = Rect {
+                x: rect.x + PADDING,
+                y: rect.y + HEADER,
+                w: rect.w - 2.0 * PADDING,
+                h: rect.h - HEADER - PADDING,
+            };
+            if inner.w > 0.0 && inner.h > 0.0 {
+                render_level(svg, &node.children, inner);
+            }
+        }
+    }
+}
+
+/// Writes a label into the top-left corner of rect if it's large enough to be readable
+fn label(svg: &mut String, text: &str, rect: Rect, baseline: f64) {
+    // Rough estimate of the rendered text width at 12px
+    let needed = text.chars().count() as f64 * 7.0;
+    if rect.w < needed.min(60.0) || rect.h < 16.0 {
+        return;
+    }
+    let _ = writeln!(
+        svg,
+        r#"<text x="{:.1}" y="{:.1}" fill="white">{text}</text>"#,
+        rect.x + 4.0,
+        rect.y + baseline
+    );
+}
+
+/// Lays out values (sorted descending) inside area using the squarified algorithm
+/// by Bruls, Huizing and van Wijk, keeping rectangles close to square.
+fn squarify(values: &[f64], mut area: Rect) -> Vec<Rect> {
+    let total: f64 = values.iter().sum();
+    if total <= 0.0 {
+        return values.iter().map(|_| Rect { x: area.x, y: area.y, w: 0.0, h: 0.0 }).collect();
+    }
+    let scale = area.w * area.h / total;
+    let areas: Vec<f64> = values.iter().map(|v| v * scale).collect();
+
+    let mut out = Vec::with_capacity(areas.len());
+    let mut i = 0;
+    while i < areas.len() {
+        let side = area.w.min(area.h);
+        let mut j = i + 1;
+        while j < areas.len() && worst(&areas[i..=j], side) <= worst(&areas[i..j], side) {
+            j += 1;
+        }
+        let row = &areas[i..j];
+        let sum: f64 = row.iter().sum();
+        if area.w >= area.h {
+            // Fill a column along the left edge
+            let col_w = if area.h > 0.0 { sum / area.h } else { 0.0 };
+            let mut y = area.y;
+            for a in row {
+                let h = if col_w > 0.0 { a / col_w } else { 0.0 };
+                out.push(Rect { x: area.x, y, w: col_w, h });
+                y += h;
+            }
+            area.x += col_w;
+            area.w -= col_w;
+        } else {
+            // Fill a row along the top edge
+            let row_h = if area.w > 0.0 { sum / area.w } else { 0.0 };
+            let mut x = area.x;
+            for a in row {
+                let w = if row_h > 0.0 { a / row_h } else { 0.0 };
+                out.push(Rect { x, y: area.y, w, h: row_h });
+                x += w;
+            }
+            area.y += row_h;
+            area.h -= row_h;
+        }
+        i = j;
+    }
+    out
+}
+
+/// The worst aspect ratio of a row of areas laid out along a side of the given length
+fn worst(row: &[f64], side: f64) -> f64 {
+    let sum: f64 = row.iter().sum();
+    let max = row.iter().cloned().fold(0.0, f64::max);
+    let min = row.iter().cloned().fold(f64::INFINITY, f64::min);
+    if sum <= 0.0 || min <= 0.0 {
+        return f64::INFINITY;
+    }
+    let (s2, w2) = (sum * sum, side * side);
+    (w2 * max / s2).max(s2 / (w2 * min))
+}
+
+/// Picks a stable color for a label so the same application looks the same across runs
+fn color(label: &str) -> String {
+    let hash = label.bytes().fold(5381u32, |h, b| h.wrapping_mul(33) ^ b as u32);
+    format!("hsl({}, 55%, 45%)", hash % 360)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn approx(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    /// Checks that the rectangles tile the area with the values' share of it
+    fn assert_tiles(values: &[f64], area: Rect, rects: &[Rect]) {
+        assert_eq!(rects.len(), values.len());
+        let scale = area.w * area.h / values.iter().sum::<f64>();
+        for (v, r) in values.iter().zip(rects) {
+            assert!(approx(r.w * r.h, v * scale), "{v} laid out as {}x{}", r.w, r.h);
+            assert!(r.x >= area.x - 1e-9 && r.y >= area.y - 1e-9);
+            assert!(r.x + r.w <= area.x + area.w + 1e-9 && r.y + r.h <= area.y + area.h + 1e-9);
+        }
+        for (i, a) in rects.iter().enumerate() {
+            for b in &rects[i + 1..] {
+                let overlap_w = (a.x + a.w).min(b.x + b.w) - a.x.max(b.x);
+                let overlap_h = (a.y + a.h).min(b.y + b.h) - a.y.max(b.y);
+                assert!(overlap_w <= 1e-9 || overlap_h <= 1e-9, "rectangles overlap");
+            }
+        }
+    }
+
+    #[test]
+    fn lays_out_the_paper_example() {
+        let values = [6.0, 6.0, 4.0, 3.0, 2.0, 2.0, 1.0];
+        let area = Rect { x: 0.0, y: 0.0, w: 6.0, h: 4.0 };
+        let rects = squarify(&values, area);
+        assert_tiles(&values, area, &rects);
+        // The two largest share the first column, as in the paper
+        for (r, y) in rects[..2].iter().zip([0.0, 2.0]) {
+            assert!(approx(r.x, 0.0) && approx(r.y, y) && approx(r.w, 3.0) && approx(r.h, 2.0));
+        }
+        assert!(approx(rects[2].x, 3.0));
+    }
+
+    #[test]
+    fn keeps_rectangles_near_square() {
+        let values: Vec<f64> = (1..=20).rev().map(f64::from).collect();
+        let area = Rect { x: 10.0, y: 20.0, w: 800.0, h: 600.0 };
+        let rects = squarify(&values, area);
+        assert_tiles(&values, area, &rects);
+        for r in &rects {
+            assert!(r.w.max(r.h) / r.w.min(r.h) < 4.0, "{}x{} is too thin", r.w, r.h);
+        }
+    }
+
+    #[test]
+    fn handles_zero_values() {
+        let area = Rect { x: 0.0, y: 0.0, w: 100.0, h: 50.0 };
+        let rects = squarify(&[3.0, 1.0, 0.0], area);
+        assert_eq!(rects.len(), 3);
+        assert!(approx(rects[0].w * rects[0].h + rects[1].w * rects[1].h, 5000.0));
+        assert!(approx(rects[2].w * rects[2].h, 0.0));
+        let empty = squarify(&[0.0, 0.0], area);
+        assert!(empty.iter().all(|r| r.w == 0.0 && r.h == 0.0));
+        assert!(squarify(&[], area).is_empty());
+    }
+
+    #[test]
+    fn a_single_value_fills_the_area() {
+        let area = Rect { x: 5.0, y: 5.0, w: 30.0, h: 70.0 };
+        let r = squarify(&[42.0], area)[0];
+        assert!(approx(r.x, 5.0) && approx(r.y, 5.0) && approx(r.w, 30.0) && approx(r.h, 70.0));
+    }
+}
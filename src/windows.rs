@@ -0,0 +1,385 @@
+//! Windows support for the grouped report. Processes are enumerated with the
+//! Toolhelp API and measured with PSAPI; their working set is what Task
+//! Manager shows as memory, and corresponds to the resident set on Linux.
+//! Windows has no numeric user ids, so uid is always 0 and only the user name
+//! is reported.
+
+use std::collections::HashMap;
+use std::ffi::{OsString, c_void};
+use std::os::windows::ffi::OsStringExt;
+
+use crate::collect::{Cache, Config};
+use crate::model::ProcessSample;
+use crate::naming::process_key;
+use crate::procfs::ProcessFiles;
+
+type Handle = *mut c_void;
+
+const TH32CS_SNAPPROCESS: u32 = 0x2;
+const INVALID_HANDLE_VALUE: Handle = -1isize as Handle;
+const PROCESS_QUERY_LIMITED_INFORMATION: u32 = 0x1000;
+const PROCESS_VM_READ: u32 = 0x10;
+const TOKEN_QUERY: u32 = 0x8;
+const TOKEN_USER: u32 = 1;
+const PROCESS_COMMAND_LINE_INFORMATION: u32 = 60;
+const MAX_PATH: usize = 260;
+
+#[repr(C)]
+struct ProcessEntry32W {
+    dw_size: u32,
+    cnt_usage: u32,
+    th32_process_id: u32,
+    th32_default_heap_id: usize,
+    th32_module_id: u32,
+    cnt_threads: u32,
+    th32_parent_process_id: u32,
+    pc_pri_class_base: i32,
+    dw_flags: u32,
+    sz_exe_file: [u16; MAX_PATH],
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct ProcessMemoryCounters {
+    cb: u32,
+    page_fault_count: u32,
+    peak_working_set_size: usize,
+    working_set_size: usize,
+    quota_peak_paged_pool_usage: usize,
+    quota_paged_pool_usage: usize,
+    quota_peak_non_paged_pool_usage: usize,
+    quota_non_paged_pool_usage: usize,
+    pagefile_usage: usize,
+    peak_pagefile_usage: usize,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct MemoryStatusEx {
+    dw_length: u32,
+    dw_memory_load: u32,
+    ull_total_phys: u64,
+    ull_avail_phys: u64,
+    ull_total_page_file: u64,
+    ull_avail_page_file: u64,
+    ull_total_virtual: u64,
+    ull_avail_virtual: u64,
+    ull_avail_extended_virtual: u64,
+}
+
+/// UNICODE_STRING, as returned for the command line
+#[repr(C)]
+struct UnicodeString {
+    length: u16,
+    maximum_length: u16,
+    buffer: *const u16,
+}
+
+#[link(name = "kernel32")]
+unsafe extern "system" {
+    fn CreateToolhelp32Snapshot(flags: u32, process_id: u32) -> Handle;
+    fn Process32FirstW(snapshot: Handle, entry: *mut ProcessEntry32W) -> i32;
+    fn Process32NextW(snapshot: Handle, entry: *mut ProcessEntry32W) -> i32;
+    fn OpenProcess(access: u32, inherit: i32, process_id: u32) -> Handle;
+    fn CloseHandle(handle: Handle) -> i32;
+    fn K32GetProcessMemoryInfo(process: Handle, counters: *mut ProcessMemoryCounters, cb: u32) -> i32;
+    fn QueryFullProcessImageNameW(process: Handle, flags: u32, name: *mut u16, size: *mut u32) -> i32;
+    fn GlobalMemoryStatusEx(status: *mut MemoryStatusEx) -> i32;
+    fn GetProcessTimes(process: Handle, creation: *mut u64, exit: *mut u64, kernel: *mut u64, user: *mut u64) -> i32;
+}
+
+#[link(name = "advapi32")]
+unsafe extern "system" {
+    fn OpenProcessToken(process: Handle, access: u32, token: *mut Handle) -> i32;
+    fn GetTokenInformation(token: Handle, class: u32, info: *mut c_void, len: u32, return_len: *mut u32) -> i32;
+    fn LookupAccountSidW(
+        system: *const u16,
+        sid: *const c_void,
+        name: *mut u16,
+        name_len: *mut u32,
+        domain: *mut u16,
+        domain_len: *mut u32,
+        sid_type: *mut u32,
+    ) -> i32;
+}
+
+#[link(name = "ntdll")]
+unsafe extern "system" {
+    fn NtQueryInformationProcess(process: Handle, class: u32, info: *mut c_void, len: u32, return_len: *mut u32) -> i32;
+}
+
+/// A process handle that is closed when dropped
+struct Process(Handle);
+
+impl Process {
+    fn open(pid: &str, access: u32) -> Option<Process> {
+        // SAFETY: OpenProcess has no memory safety preconditions
+        let handle = unsafe { OpenProcess(access, 0, pid.parse().ok()?) };
+        (!handle.is_null()).then_some(Process(handle))
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        // SAFETY: the handle was opened by us and is closed exactly once
+        unsafe { CloseHandle(self.0) };
+    }
+}
+
+fn from_wide(s: &[u16]) -> String {
+    let len = s.iter().position(|c| *c == 0).unwrap_or(s.len());
+    OsString::from_wide(&s[..len]).to_string_lossy().into_owned()
+}
+
+/// Mirrors the fields of /proc/meminfo that reports need
+pub fn read_meminfo() -> HashMap<String, u64> {
+    let mut status = MemoryStatusEx { dw_length: size_of::<MemoryStatusEx>() as u32, ..Default::default() };
+    let mut info = HashMap::new();
+    // SAFETY: status is a properly sized MEMORYSTATUSEX with dwLength set
+    if unsafe { GlobalMemoryStatusEx(&mut status) } != 0 {
+        info.insert("MemTotal".to_string(), status.ull_total_phys / 1024);
+    }
+    info
+}
+
+pub fn read_hostname() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_default()
+}
+
+/// Splits a command line the way the C runtime does for argv
+fn split_command_line(line: &str) -> Vec<String> {
+    let mut args = vec![];
+    let mut current = String::new();
+    let (mut quoted, mut in_arg, mut backslashes) = (false, false, 0);
+    for c in line.chars() {
+        match c {
+            '\\' => {
+                backslashes += 1;
+                in_arg = true;
+                continue;
+            }
+            // 2n backslashes and a quote are n backslashes and a quote toggle;
+            // 2n+1 backslashes and a quote are n backslashes and a literal quote
+            '"' => {
+                current.extend(std::iter::repeat_n('\\', backslashes / 2));
+                if backslashes % 2 == 1 {
+                    current.push('"');
+                } else {
+                    quoted = !quoted;
+                }
+                in_arg = true;
+            }
+            ' ' | '\t' if !quoted => {
+                current.extend(std::iter::repeat_n('\\', backslashes));
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                }
+                in_arg = false;
+            }
+            c => {
+                current.extend(std::iter::repeat_n('\\', backslashes));
+                current.push(c);
+                in_arg = true;
+            }
+        }
+        backslashes = 0;
+    }
+    current.extend(std::iter::repeat_n('\\', backslashes));
+    if in_arg {
+        args.push(current);
+    }
+    args
+}
+
+pub fn read_cmdline(pid: &str) -> Option<Vec<String>> {
+    let process = Process::open(pid, PROCESS_QUERY_LIMITED_INFORMATION)?;
+    let mut len = 0;
+    // SAFETY: a zero-length query only reports the needed size in len
+    unsafe { NtQueryInformationProcess(process.0, PROCESS_COMMAND_LINE_INFORMATION, std::ptr::null_mut(), 0, &mut len) };
+    if len < size_of::<UnicodeString>() as u32 {
+        return None;
+    }
+    // u64 elements keep the buffer aligned for the UNICODE_STRING at its start
+    let mut buf = vec![0u64; (len as usize).div_ceil(8)];
+    // SAFETY: buf is writable for len bytes
+    if unsafe { NtQueryInformationProcess(process.0, PROCESS_COMMAND_LINE_INFORMATION, buf.as_mut_ptr().cast(), len, &mut len) } < 0 {
+        return None;
+    }
+    // SAFETY: on success, the buffer starts with a UNICODE_STRING pointing into the buffer itself
+    let line = unsafe {
+        let s = &*(buf.as_ptr() as *const UnicodeString);
+        std::slice::from_raw_parts(s.buffer, s.length as usize / 2)
+    };
+    Some(split_command_line(&from_wide(line)))
+}
+
+pub fn exe_path(pid: &str) -> Option<String> {
+    let process = Process::open(pid, PROCESS_QUERY_LIMITED_INFORMATION)?;
+    let mut name = [0u16; 1024];
+    let mut size = name.len() as u32;
+    // SAFETY: name is writable for size characters
+    if unsafe { QueryFullProcessImageNameW(process.0, 0, name.as_mut_ptr(), &mut size) } == 0 {
+        return None;
+    }
+    Some(from_wide(&name[..size as usize]))
+}
+
+/// The executable's file name without ".exe", so that e.g. java.exe is
+/// recognized as java
+pub fn exe_basename(pid: &str) -> Option<String> {
+    let path = exe_path(pid)?;
+    let name = path.rsplit('\\').next()?;
+    let stem = name.len().checked_sub(4).filter(|i| name[*i..].eq_ignore_ascii_case(".exe")).map_or(name, |i| &name[..i]);
+    Some(stem.to_string())
+}
+
+pub fn read_cmdname(pid: &str) -> Option<String> {
+    exe_basename(pid)
+}
+
+/// Windows keeps no separate process name; the image name serves as one
+pub fn read_comm(pid: &str) -> Option<String> {
+    exe_basename(pid)
+}
+
+fn read_user(process: &Process) -> Option<String> {
+    let mut token: Handle = std::ptr::null_mut();
+    // SAFETY: token receives a handle on success
+    if unsafe { OpenProcessToken(process.0, TOKEN_QUERY, &mut token) } == 0 {
+        return None;
+    }
+    let token = Process(token);
+    let mut buf = [0u64; 64];
+    let mut len = 0;
+    // SAFETY: buf is writable for its size in bytes
+    if unsafe { GetTokenInformation(token.0, TOKEN_USER, buf.as_mut_ptr().cast(), size_of_val(&buf) as u32, &mut len) } == 0 {
+        return None;
+    }
+    // TOKEN_USER starts with a pointer to the SID
+    let sid = buf[0] as *const c_void;
+    let (mut name, mut domain) = ([0u16; 256], [0u16; 256]);
+    let (mut name_len, mut domain_len, mut sid_type) = (name.len() as u32, domain.len() as u32, 0);
+    // SAFETY: sid points into buf, which is still alive; the buffers are writable for their lengths
+    let ok = unsafe {
+        LookupAccountSidW(
+            std::ptr::null(),
+            sid,
+            name.as_mut_ptr(),
+            &mut name_len,
+            domain.as_mut_ptr(),
+            &mut domain_len,
+            &mut sid_type,
+        )
+    };
+    (ok != 0).then(|| from_wide(&name))
+}
+
+/// Processes are named anew on every scan; the cache is not used
+pub fn collect_processes(config: &Config, _cache: &mut Cache) -> Vec<ProcessSample> {
+    // SAFETY: CreateToolhelp32Snapshot has no memory safety preconditions
+    let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) };
+    if snapshot == INVALID_HANDLE_VALUE {
+        eprintln!("Failed to list processes: {}", std::io::Error::last_os_error());
+        std::process::exit(1);
+    }
+    let snapshot = Process(snapshot);
+
+    let mut processes = Vec::new();
+    // SAFETY: PROCESSENTRY32W is plain data, for which all zeroes is valid
+    let mut entry: ProcessEntry32W = unsafe { std::mem::zeroed() };
+    entry.dw_size = size_of::<ProcessEntry32W>() as u32;
+    // SAFETY: entry is a properly sized PROCESSENTRY32W with dwSize set
+    let mut more = unsafe { Process32FirstW(snapshot.0, &mut entry) } != 0;
+    while more {
+        let pid = entry.th32_process_id.to_string();
+        if let Some(sample) = sample_process(&pid, entry.th32_parent_process_id, entry.cnt_threads, config) {
+            processes.push(sample);
+        }
+        // SAFETY: as above
+        more = unsafe { Process32NextW(snapshot.0, &mut entry) } != 0;
+    }
+    processes
+}
+
+/// Seconds between 1601-01-01, the FILETIME epoch, and the Unix epoch
+const FILETIME_UNIX_OFFSET: u64 = 11_644_473_600;
+
+/// When the process was started, in seconds since the Unix epoch
+fn start_time(process: &Process) -> Option<u64> {
+    let (mut creation, mut exit, mut kernel, mut user) = (0u64, 0u64, 0u64, 0u64);
+    // SAFETY: each pointer refers to a writable FILETIME-sized value
+    if unsafe { GetProcessTimes(process.0, &mut creation, &mut exit, &mut kernel, &mut user) } == 0 {
+        return None;
+    }
+    // FILETIMEs count 100 ns intervals
+    (creation / 10_000_000).checked_sub(FILETIME_UNIX_OFFSET)
+}
+
+fn sample_process(pid: &str, ppid: u32, threads: u32, config: &Config) -> Option<ProcessSample> {
+    // Processes of other users and protected processes can't be opened; skip them quietly
+    let process = Process::open(pid, PROCESS_QUERY_LIMITED_INFORMATION | PROCESS_VM_READ)
+        .or_else(|| Process::open(pid, PROCESS_QUERY_LIMITED_INFORMATION))?;
+    let mut counters = ProcessMemoryCounters { cb: size_of::<ProcessMemoryCounters>() as u32, ..Default::default() };
+    // SAFETY: counters is a properly sized PROCESS_MEMORY_COUNTERS with cb set
+    if unsafe { K32GetProcessMemoryInfo(process.0, &mut counters, counters.cb) } == 0 || counters.working_set_size == 0 {
+        return None;
+    }
+    Some(ProcessSample {
+        pid: pid.parse().ok()?,
+        ppid,
+        // Windows sessions are logon sessions, not groups of related processes
+        sid: 0,
+        uid: 0,
+        user: read_user(&process).unwrap_or_default(),
+        name: process_key(&ProcessFiles::new(pid), config.name_from, config.java)?,
+        rss_kb: counters.working_set_size as u64 / 1024,
+        hugetlb_kb: 0,
+        locked_kb: 0,
+        hwm_kb: counters.peak_working_set_size as u64 / 1024,
+        peak_kb: 0,
+        swap_kb: 0,
+        threads: threads.into(),
+        gpu_kb: None,
+        pss_kb: None,
+        uss_kb: None,
+        start_time: if config.age { start_time(&process) } else { None },
+        breakdown: None,
+        dirty: None,
+        numa: None,
+        cgroup: None,
+        cgroup_memory: None,
+        container: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_command_line as split;
+
+    #[test]
+    fn splits_at_whitespace() {
+        assert_eq!(split(r"C:\Windows\notepad.exe  a.txt	b.txt "), [r"C:\Windows\notepad.exe", "a.txt", "b.txt"]);
+        assert!(split("").is_empty());
+        assert!(split("  \t ").is_empty());
+    }
+
+    #[test]
+    fn keeps_quoted_whitespace() {
+        assert_eq!(split(r#""C:\Program Files\App\app.exe" --name "two words""#), [r"C:\Program Files\App\app.exe", "--name", "two words"]);
+        assert_eq!(split(r#"a"b c"d e"#), ["ab cd", "e"]);
+        assert_eq!(split(r#"app "" x"#), ["app", "", "x"]);
+        // An unterminated quote runs to the end
+        assert_eq!(split(r#"app "a b"#), ["app", "a b"]);
+    }
+
+    #[test]
+    fn handles_backslashes_before_quotes() {
+        // Backslashes are literal unless they precede a quote
+        assert_eq!(split(r"a\\b c\"), [r"a\\b", r"c\"]);
+        // 2n backslashes and a quote: n backslashes, and the quote toggles quoting
+        assert_eq!(split(r#""a\\" b"#), [r"a\", "b"]);
+        // 2n+1 backslashes and a quote: n backslashes and a literal quote
+        assert_eq!(split(r#"a\"b c\\\"d"#), [r#"a"b"#, r#"c\"d"#]);
+        assert_eq!(split(r#""say \"hi\"" x"#), [r#"say "hi""#, "x"]);
+    }
+}